@@ -0,0 +1,107 @@
+use std::f32::consts::PI;
+
+use crate::game::Heading;
+
+/* Sub-steps each step of driving is integrated and collision checked in */
+const SUBSTEPS: usize = 8;
+
+/* Spacing of the points sampled along the suction head, in squares */
+const HEAD_SAMPLE: f32 = 0.25;
+
+/* Pose of a robot moving freely over the floor, in units of squares: (x, y)
+ * is the middle of its body, and theta its heading in radians clockwise from
+ * up, so that 0 is Heading::Up and PI / 2 is Heading::Right. */
+#[derive(Clone, Copy, Debug)]
+pub struct Pose {
+    pub x: f32,
+    pub y: f32,
+    pub theta: f32,
+}
+
+/* A differential drive command: the distance each wheel turns through in
+ * one step, in squares. Negative distances drive backwards. */
+#[derive(Clone, Copy, Debug)]
+pub struct Drive {
+    pub left: f32,
+    pub right: f32,
+}
+
+impl Drive {
+    /* Both wheels together, straight forwards or backwards */
+    pub fn straight(d: f32) -> Drive {
+        Drive{left: d, right: d}
+    }
+    /* Wheels in opposite directions, turning on the spot by an angle
+     * (clockwise) for a robot with the given wheelbase */
+    pub fn spin(angle: f32, wheelbase: f32) -> Drive {
+        Drive{left: angle * wheelbase / 2.0, right: -angle * wheelbase / 2.0}
+    }
+}
+
+impl Pose {
+    /* Unit vector pointing the way the robot faces */
+    pub fn forward(&self) -> (f32, f32) {
+        (self.theta.sin(), -self.theta.cos())
+    }
+    /* The poses the robot passes through driving for one step, after the
+     * current one */
+    pub fn drive(&self, d: Drive, wheelbase: f32) -> Vec<Pose> {
+        let (v, w) = ((d.left + d.right) / 2.0 / SUBSTEPS as f32, (d.left - d.right) / wheelbase / SUBSTEPS as f32);
+        let mut pose = *self;
+        (0..SUBSTEPS).map(|_| {
+            /* Midpoint integration of the arc */
+            let mid = pose.theta + w / 2.0;
+            pose.x += v * mid.sin();
+            pose.y -= v * mid.cos();
+            pose.theta = (pose.theta + w).rem_euclid(2.0 * PI);
+            pose
+        }).collect()
+    }
+    /* The nearest of the grid headings, diagonals included or not */
+    pub fn heading(&self, diagonal: bool) -> Heading {
+        let eighths = if diagonal {
+            (self.theta / (PI / 4.0)).round() as i32
+        } else {
+            2 * (self.theta / (PI / 2.0)).round() as i32
+        };
+        Heading::Up.turn(eighths)
+    }
+    /* Squares under a suction head of the given width, running across the
+     * front of a round body of the given radius, from left to right */
+    pub fn head_squares(&self, radius: f32, width: f32) -> Vec<(i32, i32)> {
+        let (fx, fy) = self.forward();
+        let (cx, cy) = (self.x + fx * (radius - 0.5), self.y + fy * (radius - 0.5));
+        let n = (width / HEAD_SAMPLE).ceil() as i32;
+        let mut squares: Vec<(i32, i32)> = Vec::new();
+        for k in 0..=n {
+            /* The robot's right is (-fy, fx) */
+            let s = -width / 2.0 + width * k as f32 / n as f32;
+            let sq = ((cx - fy * s).floor() as i32, (cy + fx * s).floor() as i32);
+            if !squares.contains(&sq) {
+                squares.push(sq);
+            }
+        }
+        squares
+    }
+}
+
+/* Whether a circle overlaps the square with top-left corner (sx, sy). Each
+ * square of an obstacle is one polygon that the body can collide with. */
+pub fn circle_hits_square(cx: f32, cy: f32, r: f32, sx: i32, sy: i32) -> bool {
+    let nx = cx.clamp(sx as f32, (sx + 1) as f32);
+    let ny = cy.clamp(sy as f32, (sy + 1) as f32);
+    (cx - nx) * (cx - nx) + (cy - ny) * (cy - ny) < r * r
+}
+
+/* All squares overlapped by a circle */
+pub fn circle_squares(cx: f32, cy: f32, r: f32) -> Vec<(i32, i32)> {
+    let mut squares = Vec::new();
+    for sy in (cy - r).floor() as i32..=(cy + r).floor() as i32 {
+        for sx in (cx - r).floor() as i32..=(cx + r).floor() as i32 {
+            if circle_hits_square(cx, cy, r, sx, sy) {
+                squares.push((sx, sy));
+            }
+        }
+    }
+    squares
+}
@@ -0,0 +1,110 @@
+use std::collections::VecDeque;
+
+use rand::Rng;
+
+use crate::game::{
+    LevelConfig, Room,
+};
+use crate::metrics;
+use crate::options::Options;
+
+/* Smallest room a curriculum starts from. The charging pad and the clear
+ * area around it need about 8 squares in each direction. */
+const MIN_XSIZE: i32 = 16;
+const MIN_YSIZE: i32 = 12;
+
+const STAGES: usize = 4;
+const WINDOW: usize = 20;
+const THRESHOLD: f32 = 0.6;
+
+/* Room size and generation parameters for one stage of training */
+struct Stage {
+    xsize: i32,
+    ysize: i32,
+    config: LevelConfig,
+}
+
+/* Supplies the rooms that training episodes are played in. Without a
+ * curriculum every room is full size. With one, training starts in small,
+ * sparse rooms and moves to the next stage once the rolling mean of the
+ * fraction of dirt removed per episode crosses a threshold. */
+pub struct Curriculum {
+    stages: Vec<Stage>,
+    stage: usize,
+    window: usize,
+    threshold: f32,
+    history: VecDeque<f32>,
+}
+
+impl Curriculum {
+    /* A single stage of w x h rooms generated with the given config */
+    pub fn fixed(w: i32, h: i32, config: LevelConfig) -> Curriculum {
+        Curriculum{
+            stages: vec![Stage{xsize: w, ysize: h, config}],
+            stage: 0, window: WINDOW, threshold: THRESHOLD, history: VecDeque::new(),
+        }
+    }
+    /* Curriculum options:
+     *   --curriculum               Enable the curriculum, ending at w x h rooms
+     *   --curriculum-stages <N>    Number of stages (default: 4)
+     *   --curriculum-window <N>    Episodes in the rolling mean (default: 20)
+     *   --curriculum-threshold <f> Fraction of dirt removed to advance (default: 0.6)
+     * plus the level options of LevelConfig, which describe the final stage. */
+    pub fn from_options(opts: &Options, w: i32, h: i32) -> Curriculum {
+        let full = LevelConfig::from_options(opts);
+        let mut curriculum = Curriculum::fixed(w, h, full.clone());
+        if !opts.has("curriculum") {
+            return curriculum;
+        }
+        let n = opts.get("curriculum-stages", STAGES).max(1);
+        curriculum.stages = (0..n).map(|k| {
+            /* Interpolate from the smallest room towards the full one. Clutter
+             * grows with the stage, while dirt starts out twice as dense. */
+            let f = (k + 1) as f32 / n as f32;
+            let lerp = |lo: i32, hi: i32| lo + ((hi - lo).max(0) as f32 * f) as i32;
+            Stage{
+                xsize: lerp(MIN_XSIZE, w),
+                ysize: lerp(MIN_YSIZE, h),
+                config: LevelConfig{
+                    hazard_density: full.hazard_density * f,
+                    obstacle_density: full.obstacle_density * f,
+                    dirt_density: full.dirt_density * (2.0 - f),
+                    ..full.clone()
+                },
+            }
+        }).collect();
+        curriculum.window = opts.get("curriculum-window", WINDOW).max(1);
+        curriculum.threshold = opts.get("curriculum-threshold", THRESHOLD);
+        curriculum.announce();
+        curriculum
+    }
+    pub fn stage(&self) -> usize {
+        self.stage
+    }
+    /* A fresh room for the next episode */
+    pub fn room(&self) -> Room {
+        let stage = &self.stages[self.stage];
+        Room::with_config(stage.xsize, stage.ysize, rand::thread_rng().gen(), stage.config.clone())
+    }
+    /* Records how well an episode went, advancing a stage if warranted */
+    pub fn record(&mut self, room: &Room) {
+        self.history.push_back(room.get_dirt_coverage());
+        if self.history.len() > self.window {
+            self.history.pop_front();
+        }
+        let (mean, _, _) = metrics::stats(self.history.make_contiguous());
+        if self.history.len() == self.window && mean >= self.threshold && self.stage + 1 < self.stages.len() {
+            println!("Curriculum: rolling dirt removed {:.1}% >= {:.1}%, advancing",
+                     100.0 * mean, 100.0 * self.threshold);
+            self.stage += 1;
+            self.history.clear();
+            self.announce();
+        }
+    }
+    fn announce(&self) {
+        let s = &self.stages[self.stage];
+        println!("Curriculum: stage {}/{}: {}x{} room, hazards {:.4}, obstacles {:.4}, dirt {:.3} per square",
+                 self.stage + 1, self.stages.len(), s.xsize, s.ysize,
+                 s.config.hazard_density, s.config.obstacle_density, s.config.dirt_density);
+    }
+}
@@ -0,0 +1,109 @@
+use std::time::Instant;
+
+use tch::{
+    nn, nn::SequentialT, nn::ModuleT, nn::OptimizerConfig,
+    Tensor, Reduction
+};
+
+use crate::game;
+use crate::curriculum::Curriculum;
+use crate::explore::Explorer;
+use crate::memory::{
+    NStep, Ring, SARS,
+};
+use crate::metrics::{
+    Metrics, MetricsLog,
+};
+use crate::options::Options;
+use crate::policy;
+use crate::{
+    NUM_EPISODES, EPISODE_LEN, BATCH_SIZE, GAMMA,
+};
+
+/* Transitions kept in replay memory when --replay-capacity isn't given */
+const REPLAY_CAPACITY: usize = 50_000;
+
+/* Trains a Q-network by deep Q-learning with experience replay, on rooms
+ * from the curriculum. Options: --n-step, --gamma, --replay-capacity
+ * <transitions>, plus those of Explorer and MetricsLog. */
+pub fn train(opts: &Options, vs: &nn::VarStore, net: &SequentialT, curriculum: &mut Curriculum) {
+    let mut opt = nn::Adam::default().build(vs, 3e-4).expect("Failed to build optimiser");
+    let mut rng = rand::thread_rng();
+
+    let mut log = MetricsLog::from_options(opts, NUM_EPISODES);
+    let mut explorer = Explorer::from_options(opts, NUM_EPISODES * EPISODE_LEN / 2);
+
+    /* Replay memory, filled with n-step transitions (--n-step, --gamma) */
+    let mut rmem = Ring::<SARS>::new(opts.get("replay-capacity", REPLAY_CAPACITY));
+    let mut nstep = NStep::new(opts.get("n-step", 1), opts.get("gamma", GAMMA));
+    for ep in 0..NUM_EPISODES {
+        let mut room = curriculum.room();
+        let start = Instant::now();
+        let (mut loss_sum, mut q_sum, mut n_updates) = (0.0, 0.0, 0);
+        let mut steps = 0;
+        for _ in 0..EPISODE_LEN {
+            let s = room.get_nn_input();
+            let a = explorer.select(&policy::get_nn_q(net, &s, explorer.is_noisy()), &mut rng);
+            let r = room.perform_action(game::i_to_act(a));
+            let s_next = room.get_nn_input();
+            rmem.extend(nstep.push(s, a, r, s_next, room.is_done()));
+            steps += 1;
+            if room.is_done() {
+                break;
+            }
+            if rmem.len() >= BATCH_SIZE {
+                /* Sample from memory and learn */
+                let sample = rand::seq::index::sample(&mut rng, rmem.len(), BATCH_SIZE)
+                    .iter().map(|i| &rmem[i]).collect::<Vec<&SARS>>();
+                let mut s = Vec::with_capacity(game::SIZE_STATE * BATCH_SIZE);
+                let mut a = Vec::with_capacity(BATCH_SIZE);
+                let mut r = Vec::with_capacity(BATCH_SIZE);
+                let mut discount = Vec::with_capacity(BATCH_SIZE);
+                let mut s_next = Vec::with_capacity(game::SIZE_STATE * BATCH_SIZE);
+                for sars in sample {
+                    s.extend(sars.s);
+                    a.push(sars.a);
+                    r.push(sars.r);
+                    discount.push(sars.discount);
+                    s_next.extend(sars.s_next);
+                }
+                let q_next = net.forward_t(&Tensor::of_slice(&s_next).view((BATCH_SIZE as i64, game::SIZE_STATE as i64)), true);
+                let fwd = net.forward_t(&Tensor::of_slice(&s).view((BATCH_SIZE as i64, game::SIZE_STATE as i64)), true);
+                let max_next = Vec::from(q_next).chunks(game::SIZE_ACTION).map(|slice| {
+                    let mut max = f32::MIN;
+                    for val in slice.iter() {
+                        max = if *val > max {*val} else {max}
+                    }
+                    max
+                }).collect::<Vec<f32>>();
+                let model_r: Tensor = Tensor::of_slice(&r) + Tensor::of_slice(&discount) * Tensor::of_slice(&max_next);
+                /* Modified forward tensor with expected reward values */
+                let mut y: Vec<f32> = Vec::from(&fwd);
+                for (i, chunk) in y.chunks_mut(game::SIZE_ACTION).enumerate() {
+                    q_sum += chunk[a[i]] / BATCH_SIZE as f32;
+                    chunk[a[i]] = Vec::from(&model_r)[i];
+                }
+                let y = Tensor::of_slice(&y).view((BATCH_SIZE as i64, game::SIZE_ACTION as i64));
+                let loss = fwd.mse_loss(&y, Reduction::Mean); /* Scalar tensor */
+                loss_sum += Vec::<f32>::from(&loss)[0];
+                n_updates += 1;
+                opt.backward_step(&loss);
+            }
+        }
+        /* Episodes cut off by the step limit rather than ending keep bootstrapping */
+        rmem.extend(nstep.flush());
+        log.record(Metrics{
+            episode: ep + 1,
+            reward: room.get_total_reward(),
+            loss: if n_updates > 0 {loss_sum / n_updates as f32} else {0.0},
+            mean_q: if n_updates > 0 {q_sum / n_updates as f32} else {0.0},
+            epsilon: explorer.param(),
+            steps_per_sec: steps as f32 / start.elapsed().as_secs_f32(),
+            buffer_size: rmem.len(),
+            dirt_coverage: room.get_dirt_coverage(),
+            area_coverage: room.get_area_coverage(),
+            stage: curriculum.stage(),
+        });
+        curriculum.record(&room);
+    }
+}
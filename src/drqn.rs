@@ -0,0 +1,141 @@
+use std::time::Instant;
+
+use rand::Rng;
+
+use tch::{
+    nn, nn::OptimizerConfig,
+    Kind, Tensor, Reduction,
+};
+
+use crate::game::{
+    self, RoomVec,
+};
+use crate::curriculum::Curriculum;
+use crate::explore::Explorer;
+use crate::memory::Ring;
+use crate::metrics::{
+    Metrics, MetricsLog,
+};
+use crate::options::Options;
+use crate::recurrent::RecurrentNet;
+use crate::{
+    NUM_EPISODES, EPISODE_LEN, GAMMA,
+};
+
+/* Length of the sequences sampled from memory, and how many make a batch */
+const SEQ_LEN: usize = 16;
+const SEQ_BATCH: usize = 32;
+
+/* Episodes kept in replay memory when --replay-capacity isn't given */
+const REPLAY_CAPACITY: usize = 64;
+
+/* A complete episode in replay memory. There is one more state than there
+ * are actions: the state after the last step. Terminal episodes ended there,
+ * rather than being cut off by the step limit. */
+struct Episode {
+    states:  Vec<RoomVec>,
+    actions: Vec<usize>,
+    rewards: Vec<f32>,
+    terminal: bool,
+}
+
+/* Trains a recurrent Q-network (DRQN) on rooms from the curriculum. The
+ * hidden state is carried through each episode while playing; for learning,
+ * whole episodes are kept in memory and random sequences of them are replayed
+ * from a zero hidden state.
+ * Options: --seq-len <N> (default: 16), --gamma, --replay-capacity
+ * <episodes> (default: 64), plus those of Explorer (except noisy) and
+ * MetricsLog. */
+pub fn train(opts: &Options, vs: &nn::VarStore, net: &RecurrentNet, curriculum: &mut Curriculum) {
+    let mut opt = nn::Adam::default().build(vs, 3e-4).expect("Failed to build optimiser");
+    let mut rng = rand::thread_rng();
+    let mut log = MetricsLog::from_options(opts, NUM_EPISODES);
+    let mut explorer = Explorer::from_options(opts, NUM_EPISODES * EPISODE_LEN / 2);
+    if explorer.is_noisy() {
+        /* The recurrent net has no noisy layers, so it would never explore */
+        panic!("--explore noisy needs a noisy network, which --trainer drqn doesn't have; use epsilon or boltzmann");
+    }
+    let seq_len = opts.get("seq-len", SEQ_LEN).clamp(1, EPISODE_LEN);
+    let gamma: f64 = opts.get("gamma", GAMMA as f64);
+
+    let mut memory: Ring<Episode> = Ring::new(opts.get("replay-capacity", REPLAY_CAPACITY));
+    for ep in 0..NUM_EPISODES {
+        let mut room = curriculum.room();
+        let start = Instant::now();
+        let (mut loss_sum, mut q_sum, mut n_updates) = (0.0, 0.0, 0);
+        let mut episode = Episode{
+            states: Vec::with_capacity(EPISODE_LEN + 1),
+            actions: Vec::with_capacity(EPISODE_LEN),
+            rewards: Vec::with_capacity(EPISODE_LEN),
+            terminal: false,
+        };
+        let mut hidden = net.zero_state(1);
+        for _ in 0..EPISODE_LEN {
+            let s = room.get_nn_input();
+            let q = tch::no_grad(|| {
+                let (q, h_next) = net.seq(&Tensor::of_slice(&s).view((1, 1, game::SIZE_STATE as i64)), &hidden);
+                hidden = h_next;
+                Vec::<f32>::from(q)
+            });
+            let a = explorer.select(&q, &mut rng);
+            let r = room.perform_action(game::i_to_act(a));
+            episode.states.push(s);
+            episode.actions.push(a);
+            episode.rewards.push(r);
+
+            if !memory.is_empty() {
+                /* Sample sequences from past episodes and learn */
+                let mut s: Vec<f32> = Vec::with_capacity(SEQ_BATCH * (seq_len + 1) * game::SIZE_STATE);
+                let mut a = Vec::with_capacity(SEQ_BATCH * seq_len);
+                let mut r: Vec<f32> = Vec::with_capacity(SEQ_BATCH * seq_len);
+                let mut m: Vec<f32> = Vec::with_capacity(SEQ_BATCH * seq_len);
+                for _ in 0..SEQ_BATCH {
+                    let e = &memory[rng.gen_range(0..memory.len())];
+                    let t0 = rng.gen_range(0..=(e.actions.len() - seq_len));
+                    e.states[t0..=(t0 + seq_len)].iter().for_each(|st| s.extend(st));
+                    a.extend(e.actions[t0..(t0 + seq_len)].iter().map(|a| *a as i64));
+                    r.extend(&e.rewards[t0..(t0 + seq_len)]);
+                    /* Don't bootstrap past the end of a terminal episode */
+                    m.extend((t0..(t0 + seq_len)).map(|t| if e.terminal && t + 1 == e.actions.len() {0.0} else {1.0}));
+                }
+                let (batch, len) = (SEQ_BATCH as i64, seq_len as i64);
+                let s = Tensor::of_slice(&s).view((batch, len + 1, game::SIZE_STATE as i64));
+                let a = Tensor::of_slice(&a).view((batch, len, 1));
+                let r = Tensor::of_slice(&r).view((batch, len));
+                let m = Tensor::of_slice(&m).view((batch, len));
+                let (q_all, _) = net.seq(&s, &net.zero_state(batch));
+                let q = q_all.narrow(1, 0, len).gather(2, &a, false).squeeze_dim(2);
+                let (max_next, _) = q_all.narrow(1, 1, len).detach().max_dim(2, false);
+                let y = r + gamma * m * max_next;
+                let loss = q.mse_loss(&y, Reduction::Mean);
+                q_sum += Vec::<f32>::from(&q.mean(Kind::Float))[0];
+                loss_sum += Vec::<f32>::from(&loss)[0];
+                n_updates += 1;
+                opt.backward_step(&loss);
+            }
+            if room.is_done() {
+                break;
+            }
+        }
+        episode.states.push(room.get_nn_input());
+        episode.terminal = room.is_done();
+        let steps = episode.actions.len();
+        /* Episodes that ended too soon have no whole sequences to learn from */
+        if steps >= seq_len {
+            memory.push(episode);
+        }
+        log.record(Metrics{
+            episode: ep + 1,
+            reward: room.get_total_reward(),
+            loss: if n_updates > 0 {loss_sum / n_updates as f32} else {0.0},
+            mean_q: if n_updates > 0 {q_sum / n_updates as f32} else {0.0},
+            epsilon: explorer.param(),
+            steps_per_sec: steps as f32 / start.elapsed().as_secs_f32(),
+            buffer_size: memory.iter().map(|e| e.actions.len()).sum(),
+            dirt_coverage: room.get_dirt_coverage(),
+            area_coverage: room.get_area_coverage(),
+            stage: curriculum.stage(),
+        });
+        curriculum.record(&room);
+    }
+}
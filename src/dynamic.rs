@@ -0,0 +1,83 @@
+use rand::{
+    Rng, rngs::StdRng,
+};
+
+/* Chance a mover takes a step each time the robots do, so that they're
+ * slower than the robots */
+const MOVE_CHANCE: f32 = 0.5;
+
+/* How a dynamic obstacle gets about */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Motion {
+    Wander, /* A random walk, like a pet */
+    Patrol, /* Back and forth along a line, like a person pacing */
+    Follow, /* Towards the robot, like a curious pet */
+}
+
+/* Something that moves around the room by itself, occupying size x size
+ * squares with its top-left corner at (x, y) */
+#[derive(Clone)]
+pub struct Mover {
+    pub x: i32,
+    pub y: i32,
+    pub size: i32,
+    pub motion: Motion,
+    /* Patrol: the ends of the line, and the one it's heading for */
+    ends: [(i32, i32); 2],
+    target: usize,
+}
+
+/* One square towards a target, along each axis */
+fn towards(from: (i32, i32), to: (i32, i32)) -> (i32, i32) {
+    ((to.0 - from.0).signum(), (to.1 - from.1).signum())
+}
+
+impl Mover {
+    pub fn new(x: i32, y: i32, size: i32, motion: Motion, patrol_to: (i32, i32)) -> Mover {
+        Mover{x, y, size, motion, ends: [(x, y), patrol_to], target: 1}
+    }
+    /* Squares it covers when at (x, y) */
+    pub fn squares_at(&self, x: i32, y: i32) -> Vec<(i32, i32)> {
+        (0..self.size).flat_map(|dy| (0..self.size).map(move |dx| (x + dx, y + dy))).collect()
+    }
+    pub fn squares(&self) -> Vec<(i32, i32)> {
+        self.squares_at(self.x, self.y)
+    }
+    /* Where it would like to move next, at most one square away, if
+     * anywhere. `goal` is where a follower is drawn to. */
+    pub fn next(&mut self, rng: &mut StdRng, goal: (i32, i32)) -> Option<(i32, i32)> {
+        if rng.gen::<f32>() >= MOVE_CHANCE {
+            return None;
+        }
+        let (dx, dy) = match self.motion {
+            Motion::Wander => [(0, -1), (1, 0), (0, 1), (-1, 0)][rng.gen_range(0..4)],
+            Motion::Patrol => {
+                if (self.x, self.y) == self.ends[self.target] {
+                    self.target = 1 - self.target;
+                }
+                towards((self.x, self.y), self.ends[self.target])
+            }
+            Motion::Follow => {
+                /* Stop short of the goal rather than pushing into it */
+                let (gx, gy) = (goal.0 - self.x, goal.1 - self.y);
+                if gx.abs() + gy.abs() <= self.size + 2 {
+                    (0, 0)
+                } else if gx.abs() > gy.abs() {
+                    (gx.signum(), 0)
+                } else {
+                    (0, gy.signum())
+                }
+            }
+        };
+        if (dx, dy) == (0, 0) {None} else {Some((self.x + dx, self.y + dy))}
+    }
+    /* Moves to where next() asked to go, if it's free */
+    pub fn advance(&mut self, to: (i32, i32), free: bool) {
+        if free {
+            (self.x, self.y) = to;
+        } else if self.motion == Motion::Patrol {
+            /* Something's in the way, turn back */
+            self.target = 1 - self.target;
+        }
+    }
+}
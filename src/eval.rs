@@ -0,0 +1,217 @@
+use std::fs;
+use std::io::{
+    BufWriter, Write,
+};
+
+use crate::game::{
+    self, LevelConfig, Room,
+};
+use crate::metrics::stats;
+use crate::options::Options;
+use crate::policy::Policy;
+
+/* Size of generated rooms in the default suite, independent of the terminal */
+const SUITE_XSIZE: i32 = 60;
+const SUITE_YSIZE: i32 = 30;
+const SUITE_SEEDS: u64 = 16;
+
+/* A level in the benchmark suite */
+pub enum Level {
+    Seed(u64, i32, i32), /* Seed, xsize, ysize */
+    Map(String),         /* Path to a map file */
+}
+
+impl Level {
+    pub fn name(&self) -> String {
+        match self {
+            Level::Seed(seed, xsize, ysize) => format!("seed:{}:{}x{}", seed, xsize, ysize),
+            Level::Map(path) => format!("map:{}", path),
+        }
+    }
+    /* The room for an episode on this level. Seeded levels give each
+     * episode its own seed, so that episodes differ. */
+    pub fn room(&self, config: &LevelConfig, episode: usize) -> Room {
+        match self {
+            Level::Seed(seed, xsize, ysize) =>
+                Room::with_config(*xsize, *ysize, seed ^ ((episode as u64) << 32), config.clone()),
+            Level::Map(path) => {
+                let mut room = Room::from_map(path)
+                    .unwrap_or_else(|e| panic!("Failed to load map: {}", e));
+                room.set_config(config.clone());
+                room
+            }
+        }
+    }
+}
+
+/* The suite used when none is given: a fixed set of seeded rooms */
+pub fn default_suite() -> Vec<Level> {
+    (0..SUITE_SEEDS).map(|seed| Level::Seed(seed, SUITE_XSIZE, SUITE_YSIZE)).collect()
+}
+
+/* Reads a suite file. Each line is one of:
+ *   seed <seed> [<xsize> <ysize>]
+ *   map <path>
+ * Blank lines and lines beginning with '#' are ignored. */
+pub fn load_suite(path: &str) -> Vec<Level> {
+    let text = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Failed to read suite {}: {}", path, e));
+    let mut suite = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let level = match words.as_slice() {
+            [] => continue,
+            [w, ..] if w.starts_with('#') => continue,
+            ["seed", seed] => seed.parse().ok()
+                .map(|seed| Level::Seed(seed, SUITE_XSIZE, SUITE_YSIZE)),
+            ["seed", seed, xsize, ysize] => match (seed.parse(), xsize.parse(), ysize.parse()) {
+                (Ok(seed), Ok(xsize), Ok(ysize)) => Some(Level::Seed(seed, xsize, ysize)),
+                _ => None,
+            },
+            ["map", path] => Some(Level::Map(path.to_string())),
+            _ => None,
+        };
+        match level {
+            Some(level) => suite.push(level),
+            None => panic!("{}:{}: expected 'seed <seed> [<xsize> <ysize>]' or 'map <path>'", path, n + 1),
+        }
+    }
+    suite
+}
+
+/* Outcome of a single evaluation episode */
+pub struct EpisodeResult {
+    pub policy: String,
+    pub level: String,
+    pub episode: usize,
+    pub reward: f32,
+    pub dirt_coverage: f32,
+    pub area_coverage: f32,
+}
+
+/* Plays a policy greedily for a number of episodes on every level of the
+ * suite, with the level options it was trained with */
+pub fn run_policy(name: &str, policy: &mut Policy, suite: &[Level], config: &LevelConfig, episodes: usize, steps: usize)
+    -> Vec<EpisodeResult> {
+    let mut results = Vec::with_capacity(suite.len() * episodes);
+    for level in suite {
+        for episode in 0..episodes {
+            let mut room = level.room(config, episode);
+            policy.reset();
+            for _ in 0..steps {
+                let a = policy.best_action(0, &room.get_nn_input());
+                room.perform_action(game::i_to_act(a));
+                if room.is_done() {
+                    break;
+                }
+            }
+            results.push(EpisodeResult{
+                policy: name.to_string(),
+                level: level.name(),
+                episode,
+                reward: room.get_total_reward(),
+                dirt_coverage: room.get_dirt_coverage(),
+                area_coverage: room.get_area_coverage(),
+            });
+        }
+    }
+    results
+}
+
+fn print_summary(name: &str, results: &[EpisodeResult]) {
+    let rewards: Vec<f32> = results.iter().map(|r| r.reward).collect();
+    let dirt: Vec<f32> = results.iter().map(|r| r.dirt_coverage).collect();
+    let area: Vec<f32> = results.iter().map(|r| r.area_coverage).collect();
+    let (mean, std, median) = stats(&rewards);
+    println!("{}: {} episodes", name, results.len());
+    println!("  Reward:        mean {:8.1}  std {:8.1}  median {:8.1}", mean, std, median);
+    println!("  Dirt removed:  mean {:7.1}%", 100.0 * stats(&dirt).0);
+    println!("  Area swept:    mean {:7.1}%", 100.0 * stats(&area).0);
+}
+
+/* Compares the mean reward of two policies on each level of the suite */
+fn print_comparison(suite: &[Level], a: &[EpisodeResult], b: &[EpisodeResult]) {
+    let (mut wins, mut draws, mut losses) = (0, 0, 0);
+    println!("{:<32} {:>10} {:>10}", "Level", "A", "B");
+    for level in suite {
+        let name = level.name();
+        let mean_reward = |results: &[EpisodeResult]| stats(&results.iter()
+            .filter(|r| r.level == name)
+            .map(|r| r.reward)
+            .collect::<Vec<f32>>()).0;
+        let (ra, rb) = (mean_reward(a), mean_reward(b));
+        let mark = if ra > rb {
+            wins += 1;
+            "A"
+        } else if ra < rb {
+            losses += 1;
+            "B"
+        } else {
+            draws += 1;
+            "="
+        };
+        println!("{:<32} {:>10.1} {:>10.1}  {}", name, ra, rb, mark);
+    }
+    println!("A wins {}, B wins {}, draws {}", wins, losses, draws);
+}
+
+/* Writes per-episode results as JSON if the path ends in .json, CSV otherwise */
+pub fn write_results(path: &str, results: &[EpisodeResult]) -> std::io::Result<()> {
+    let mut f = BufWriter::new(fs::File::create(path)?);
+    if path.ends_with(".json") {
+        writeln!(f, "[")?;
+        for (i, r) in results.iter().enumerate() {
+            writeln!(f, "  {{\"policy\": {:?}, \"level\": {:?}, \"episode\": {}, \"reward\": {}, \
+                         \"dirt_coverage\": {}, \"area_coverage\": {}}}{}",
+                     r.policy, r.level, r.episode, r.reward, r.dirt_coverage, r.area_coverage,
+                     if i + 1 < results.len() {","} else {""})?;
+        }
+        writeln!(f, "]")?;
+    } else {
+        writeln!(f, "policy,level,episode,reward,dirt_coverage,area_coverage")?;
+        for r in results {
+            writeln!(f, "{},{},{},{},{},{}",
+                     r.policy, r.level, r.episode, r.reward, r.dirt_coverage, r.area_coverage)?;
+        }
+    }
+    f.flush()
+}
+
+/* Entry point for `eval` mode:
+ *   --policy <file>    Checkpoint to evaluate (required)
+ *   --against <file>   Second checkpoint to compare head-to-head
+ *   --suite <file>     Suite of levels (default: 16 seeded 60x30 rooms)
+ *   --episodes <K>     Episodes per level (default: 1). Each episode on a
+ *                      seeded level has a seed of its own; a map plays out
+ *                      the same every time, so needs only one.
+ *   --steps <N>        Steps per episode (default: EPISODE_LEN)
+ *   --out <file>       Per-episode results, .csv or .json
+ * plus the level options of LevelConfig, which should match training. */
+pub fn run(opts: &Options) {
+    let suite = match opts.get_str("suite") {
+        Some(path) => load_suite(path),
+        None => default_suite(),
+    };
+    let episodes = opts.get("episodes", 1);
+    let steps = opts.get("steps", crate::EPISODE_LEN);
+    let path_a = opts.get_str("policy").expect("eval requires --policy <checkpoint>");
+    let config = LevelConfig::from_options(opts);
+
+    let (_vs_a, mut policy_a) = Policy::load(path_a);
+    let mut results = run_policy(path_a, &mut policy_a, &suite, &config, episodes, steps);
+    print_summary(path_a, &results);
+
+    if let Some(path_b) = opts.get_str("against") {
+        let (_vs_b, mut policy_b) = Policy::load(path_b);
+        let results_b = run_policy(path_b, &mut policy_b, &suite, &config, episodes, steps);
+        print_summary(path_b, &results_b);
+        println!("A: {}\nB: {}", path_a, path_b);
+        print_comparison(&suite, &results, &results_b);
+        results.extend(results_b);
+    }
+
+    if let Some(path) = opts.get_str("out") {
+        write_results(path, &results).expect("Failed to write results");
+        println!("Results written to {}", path);
+    }
+}
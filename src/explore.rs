@@ -0,0 +1,130 @@
+use rand::Rng;
+
+use crate::game;
+use crate::options::Options;
+
+/* How a parameter (epsilon or temperature) changes over training steps */
+pub enum Schedule {
+    Constant(f32),
+    Linear{start: f32, end: f32, steps: usize},
+    Exponential{start: f32, end: f32, steps: usize}, /* steps is the time constant */
+}
+
+impl Schedule {
+    pub fn value(&self, step: usize) -> f32 {
+        match self {
+            Schedule::Constant(v) => *v,
+            Schedule::Linear{start, end, steps} => {
+                let t = (step as f32 / (*steps).max(1) as f32).min(1.0);
+                start + (end - start) * t
+            }
+            Schedule::Exponential{start, end, steps} => {
+                end + (start - end) * (-(step as f32) / (*steps).max(1) as f32).exp()
+            }
+        }
+    }
+}
+
+pub enum Strategy {
+    /* Random action with probability epsilon, otherwise the best one */
+    EpsilonGreedy(Schedule),
+    /* Sample from softmax(Q / temperature) */
+    Boltzmann(Schedule),
+    /* Greedy over a noisy network, which explores through its own weights */
+    Noisy,
+}
+
+/* Chooses actions from Q-values during training. The first `warmup` steps
+ * are played purely at random to fill the replay memory. */
+pub struct Explorer {
+    strategy: Strategy,
+    warmup: usize,
+    step: usize,
+}
+
+impl Explorer {
+    /* Exploration options:
+     *   --explore <epsilon|boltzmann|noisy>  Strategy (default: epsilon)
+     *   --schedule <constant|linear|exp>     Decay of epsilon/temperature (default: constant)
+     *   --eps-start, --eps-end               Epsilon range (default: 0.2 -> 0.05)
+     *   --temp-start, --temp-end             Temperature range (default: 1.0 -> 0.1)
+     *   --decay-steps <N>                    Length of the decay (default: decay_steps)
+     *   --warmup <N>                         Steps of pure random play (default: 0) */
+    pub fn from_options(opts: &Options, decay_steps: usize) -> Explorer {
+        let steps = opts.get("decay-steps", decay_steps);
+        let schedule = |start: f32, end: f32| match opts.get_str("schedule").unwrap_or("constant") {
+            "constant" => Schedule::Constant(start),
+            "linear" => Schedule::Linear{start, end, steps},
+            "exp" => Schedule::Exponential{start, end, steps},
+            s => panic!("Unknown schedule '{}', expected constant, linear or exp", s),
+        };
+        let strategy = match opts.get_str("explore").unwrap_or("epsilon") {
+            "epsilon" => Strategy::EpsilonGreedy(schedule(opts.get("eps-start", 0.2), opts.get("eps-end", 0.05))),
+            "boltzmann" => Strategy::Boltzmann(schedule(opts.get("temp-start", 1.0), opts.get("temp-end", 0.1))),
+            "noisy" => Strategy::Noisy,
+            s => panic!("Unknown exploration strategy '{}', expected epsilon, boltzmann or noisy", s),
+        };
+        Explorer{strategy, warmup: opts.get("warmup", 0), step: 0}
+    }
+    /* Whether Q-values should come from a noisy network in training mode */
+    pub fn is_noisy(&self) -> bool {
+        matches!(self.strategy, Strategy::Noisy)
+    }
+    /* Current epsilon or temperature, for logging. 1 during warm-up. */
+    pub fn param(&self) -> f32 {
+        if self.step < self.warmup {
+            return 1.0;
+        }
+        match &self.strategy {
+            Strategy::EpsilonGreedy(s) | Strategy::Boltzmann(s) => s.value(self.step - self.warmup),
+            Strategy::Noisy => 0.0,
+        }
+    }
+    pub fn select<R: Rng>(&mut self, q: &[f32], rng: &mut R) -> usize {
+        let param = self.param();
+        let warmup = self.step < self.warmup;
+        self.step += 1;
+        if warmup {
+            return rng.gen_range(0..game::SIZE_ACTION);
+        }
+        match &self.strategy {
+            Strategy::EpsilonGreedy(_) => {
+                if rng.gen::<f32>() < param {
+                    rng.gen_range(0..game::SIZE_ACTION)
+                } else {
+                    argmax(q)
+                }
+            }
+            Strategy::Boltzmann(_) => {
+                let max = q.iter().cloned().fold(f32::MIN, f32::max);
+                let weights: Vec<f32> = q.iter().map(|v| ((v - max) / param.max(1e-6)).exp()).collect();
+                sample(&weights, rng)
+            }
+            Strategy::Noisy => argmax(q),
+        }
+    }
+}
+
+pub fn argmax(values: &[f32]) -> usize {
+    let mut max = f32::MIN;
+    let mut argmax = 0;
+    for (i, v) in values.iter().enumerate() {
+        if *v > max {
+            max = *v;
+            argmax = i;
+        }
+    }
+    argmax
+}
+
+/* Samples an index with probability proportional to its (non-negative) weight */
+pub fn sample<R: Rng>(weights: &[f32], rng: &mut R) -> usize {
+    let mut x = rng.gen::<f32>() * weights.iter().sum::<f32>();
+    for (i, w) in weights.iter().enumerate() {
+        if x < *w {
+            return i;
+        }
+        x -= w;
+    }
+    weights.len() - 1
+}
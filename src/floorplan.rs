@@ -0,0 +1,237 @@
+use rand::{
+    Rng, rngs::StdRng,
+};
+
+use crate::game::Cell;
+
+/* Smallest room across */
+const MIN_ROOM: i32 = 10;
+
+/* Chance that a split makes a hallway between the two sides rather than a
+ * single wall */
+const HALLWAY_CHANCE: f32 = 0.25;
+
+/* Height of the gap under furniture standing on legs, in cm. Robots lower
+ * than this drive underneath, between the legs. Chairs count as tables. */
+pub const TABLE: i32 = 40;
+pub const BED: i32 = 12;
+pub const SOFA: i32 = 6;
+
+/* A rectangle of squares, from (x0, y0) up to but not including (x1, y1) */
+#[derive(Clone, Copy)]
+pub struct Rect {
+    pub x0: i32,
+    pub y0: i32,
+    pub x1: i32,
+    pub y1: i32,
+}
+
+impl Rect {
+    fn width(&self) -> i32 {
+        self.x1 - self.x0
+    }
+    fn height(&self) -> i32 {
+        self.y1 - self.y0
+    }
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x0 && x < self.x1 && y >= self.y0 && y < self.y1
+    }
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        self.x0 < other.x1 && other.x0 < self.x1 && self.y0 < other.y1 && other.y0 < self.y1
+    }
+}
+
+/* A floor plan: walls and furniture, the height of the gap under furniture
+ * over each square (0: nothing overhead), and the areas around doorways that
+ * clutter is kept out of. Clutter can still cut off a room elsewhere, so the
+ * level clears a way through afterwards. */
+pub struct Plan {
+    pub cells: Vec<Cell>,
+    pub clearance: Vec<i32>,
+    pub clear: Vec<Rect>,
+}
+
+struct Builder<'a> {
+    xsize: i32,
+    ysize: i32,
+    door: i32,
+    pad: Rect,
+    cells: Vec<Cell>,
+    clearance: Vec<i32>,
+    clear: Vec<Rect>,
+    rng: &'a mut StdRng,
+}
+
+/* Divides a room into about `rooms` rooms by binary space partitioning, with
+ * walls between them, doorways `door` squares wide through the walls and
+ * the odd hallway. Each room gets cupboards, sofas and beds along its walls,
+ * and the larger ones a table with chairs in the middle. Nothing goes in
+ * `pad`, the corner around the charging pad. */
+pub fn generate(xsize: i32, ysize: i32, rooms: usize, door: i32, pad: Rect, rng: &mut StdRng) -> Plan {
+    let n = (xsize * ysize) as usize;
+    let mut b = Builder{xsize, ysize, door, pad, cells: vec![Cell::Floor(0); n], clearance: vec![0; n],
+                        clear: Vec::new(), rng};
+    let mut leaves = vec![Rect{x0: 0, y0: 0, x1: xsize, y1: ysize}];
+    while leaves.len() < rooms {
+        /* Split the largest room that will go */
+        leaves.sort_by_key(|r| -r.width() * r.height());
+        match (0..leaves.len()).find_map(|i| b.split(&leaves[i]).map(|halves| (i, halves))) {
+            Some((i, (r1, r2))) => {
+                leaves.swap_remove(i);
+                leaves.push(r1);
+                leaves.push(r2);
+            }
+            None => break,
+        }
+    }
+    for leaf in &leaves {
+        b.furnish(leaf);
+    }
+    Plan{cells: b.cells, clearance: b.clearance, clear: b.clear}
+}
+
+impl Builder<'_> {
+    fn set(&mut self, x: i32, y: i32, cell: Cell) {
+        self.cells[(y * self.xsize + x) as usize] = cell;
+    }
+    fn is_door(&self, x: i32, y: i32) -> bool {
+        self.clear.iter().any(|r| r.contains(x, y))
+    }
+    /* Splits a room across its longer side, returning the two halves, or None
+     * if it's too small */
+    fn split(&mut self, r: &Rect) -> Option<(Rect, Rect)> {
+        let vertical = r.width() >= r.height();
+        let (lo, hi) = if vertical {(r.x0, r.x1)} else {(r.y0, r.y1)};
+        let hall = if self.rng.gen::<f32>() < HALLWAY_CHANCE {self.door + 1} else {0};
+        if hi - lo < 2 * MIN_ROOM + 1 + hall {
+            return None;
+        }
+        /* A wall mustn't end in a doorway, which would narrow it */
+        for _ in 0..10 {
+            let s = self.rng.gen_range((lo + MIN_ROOM)..=(hi - MIN_ROOM - 1 - hall));
+            let ends_in_door = if vertical {
+                self.is_door(s, r.y0 - 1) || self.is_door(s, r.y1) || self.is_door(s + hall, r.y0 - 1) || self.is_door(s + hall, r.y1)
+            } else {
+                self.is_door(r.x0 - 1, s) || self.is_door(r.x1, s) || self.is_door(r.x0 - 1, s + hall) || self.is_door(r.x1, s + hall)
+            };
+            /* Nor cross the corner around the pad */
+            let across = if vertical {
+                Rect{x0: s, x1: s + hall + 1, ..*r}
+            } else {
+                Rect{y0: s, y1: s + hall + 1, ..*r}
+            };
+            if ends_in_door || across.overlaps(&self.pad) {
+                continue;
+            }
+            self.wall(r, vertical, s);
+            if hall > 0 {
+                self.wall(r, vertical, s + hall);
+            }
+            return Some(if vertical {
+                (Rect{x1: s, ..*r}, Rect{x0: s + hall + 1, ..*r})
+            } else {
+                (Rect{y1: s, ..*r}, Rect{y0: s + hall + 1, ..*r})
+            });
+        }
+        None
+    }
+    /* Builds a wall across a room at s, with a doorway in it */
+    fn wall(&mut self, r: &Rect, vertical: bool, s: i32) {
+        let (lo, hi) = if vertical {(r.y0, r.y1)} else {(r.x0, r.x1)};
+        let gap = self.rng.gen_range(lo..=(hi - self.door).max(lo));
+        for t in lo..hi {
+            if t < gap || t >= gap + self.door {
+                if vertical {self.set(s, t, Cell::Obstacle)} else {self.set(t, s, Cell::Obstacle)}
+            }
+        }
+        /* Keep clutter out of the doorway and a robot's length either side */
+        let d = self.door;
+        self.clear.push(if vertical {
+            Rect{x0: s - d, y0: gap, x1: s + d + 1, y1: gap + d}
+        } else {
+            Rect{x0: gap, y0: s - d, x1: gap + d, y1: s + d + 1}
+        });
+    }
+    /* Whether furniture could go in a rectangle: inside the room, out of
+     * doorways and away from the charging pad */
+    fn fits(&self, f: &Rect) -> bool {
+        f.x0 >= 0 && f.y0 >= 0 && f.x1 <= self.xsize && f.y1 <= self.ysize
+            && !f.overlaps(&self.pad) && !self.clear.iter().any(|c| c.overlaps(f))
+    }
+    /* Furniture that reaches the floor all over, like a cupboard */
+    fn solid(&mut self, f: &Rect) {
+        for x in f.x0..f.x1 {
+            for y in f.y0..f.y1 {
+                self.set(x, y, Cell::Obstacle);
+            }
+        }
+    }
+    /* Furniture on legs at its corners, with a gap of the given height
+     * underneath */
+    fn on_legs(&mut self, f: &Rect, clearance: i32) {
+        for x in f.x0..f.x1 {
+            for y in f.y0..f.y1 {
+                self.clearance[(y * self.xsize + x) as usize] = clearance;
+            }
+        }
+        for (x, y) in [(f.x0, f.y0), (f.x1 - 1, f.y0), (f.x0, f.y1 - 1), (f.x1 - 1, f.y1 - 1)] {
+            self.set(x, y, Cell::Obstacle);
+        }
+    }
+    /* A rectangle `depth` deep and up to `len` long against one of the walls
+     * of a room, at random */
+    fn against_wall(&mut self, r: &Rect, depth: i32, len: i32) -> Rect {
+        let f = match self.rng.gen_range(0..4) {
+            0 => Rect{x0: r.x0 + self.rng.gen_range(0..r.width()), y0: r.y0, x1: 0, y1: r.y0 + depth},
+            1 => Rect{x0: r.x0 + self.rng.gen_range(0..r.width()), y0: r.y1 - depth, x1: 0, y1: r.y1},
+            2 => Rect{x0: r.x0, y0: r.y0 + self.rng.gen_range(0..r.height()), x1: r.x0 + depth, y1: 0},
+            _ => Rect{x0: r.x1 - depth, y0: r.y0 + self.rng.gen_range(0..r.height()), x1: r.x1, y1: 0},
+        };
+        if f.x1 == 0 {Rect{x1: (f.x0 + len).min(r.x1), ..f}} else {Rect{y1: (f.y0 + len).min(r.y1), ..f}}
+    }
+    fn furnish(&mut self, r: &Rect) {
+        /* Against the walls, more in larger rooms */
+        for _ in 0..self.rng.gen_range(0..=(2 + r.width() * r.height() / 400)) {
+            match self.rng.gen_range(0..3) {
+                0 => {
+                    let (depth, len) = (self.rng.gen_range(2..=3), self.rng.gen_range(4..=10));
+                    let f = self.against_wall(r, depth, len);
+                    if self.fits(&f) {
+                        self.solid(&f);
+                    }
+                }
+                1 => {
+                    let (depth, len) = (self.rng.gen_range(3..=4), self.rng.gen_range(8..=12));
+                    let f = self.against_wall(r, depth, len);
+                    if self.fits(&f) {
+                        self.on_legs(&f, SOFA);
+                    }
+                }
+                _ => {
+                    /* The head of a bed is against the wall, so it reaches
+                     * out into the room */
+                    let (depth, len) = (self.rng.gen_range(10..=14), self.rng.gen_range(8..=12));
+                    let f = self.against_wall(r, depth, len);
+                    if self.fits(&f) {
+                        self.on_legs(&f, BED);
+                    }
+                }
+            }
+        }
+        /* A table in the middle of larger rooms, with chairs along its long
+         * sides, tucked in under the edge */
+        if r.width() >= 18 && r.height() >= 17 && self.rng.gen::<bool>() {
+            let (w, h) = (self.rng.gen_range(8..=12), self.rng.gen_range(5..=7));
+            let x0 = self.rng.gen_range((r.x0 + 3)..=(r.x1 - 3 - w));
+            let y0 = self.rng.gen_range((r.y0 + 5)..=(r.y1 - 5 - h));
+            let t = Rect{x0, y0, x1: x0 + w, y1: y0 + h};
+            if self.fits(&Rect{y0: t.y0 - 2, y1: t.y1 + 2, ..t}) {
+                self.on_legs(&t, TABLE);
+                for cx in (t.x0 + 1..t.x1 - 3).step_by(4) {
+                    self.on_legs(&Rect{x0: cx, y0: t.y0 - 2, x1: cx + 3, y1: t.y0 + 1}, TABLE);
+                    self.on_legs(&Rect{x0: cx, y0: t.y1 - 1, x1: cx + 3, y1: t.y1 + 2}, TABLE);
+                }
+            }
+        }
+    }
+}
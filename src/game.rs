@@ -1,5 +1,5 @@
 use rand::{
-    Rng, rngs::ThreadRng,
+    Rng, SeedableRng, rngs::StdRng,
 };
 
 use crossterm::{
@@ -9,16 +9,36 @@ use crossterm::{
 };
 
 use std::io::{
-    Write, stdout,
+    self, Write, stdout,
 };
 
-#[derive(PartialEq)]
+use std::fs;
+use std::collections::VecDeque;
+
+use crate::continuous::{
+    self, Drive, Pose,
+};
+use crate::dynamic::{
+    Motion, Mover,
+};
+use crate::floorplan::{
+    self, Rect,
+};
+use crate::mission::{
+    Area, Mission,
+};
+use crate::options::Options;
+
+#[derive(Clone, Copy, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Action {
     FORWARD,
     REVERSE,
     L,
     R,
     SUCK,
+    DOCK,
+    UNDOCK,
 }
 
 pub fn i_to_act(i: usize) -> Action {
@@ -28,89 +48,864 @@ pub fn i_to_act(i: usize) -> Action {
         2 => Action::L,
         3 => Action::R,
         4 => Action::SUCK,
+        5 => Action::DOCK,
+        6 => Action::UNDOCK,
         _ => Action::FORWARD,
     }
 }
 
-pub struct Room {
-    /* Game board, stored in an array (xsize * ysize) in length 
-     * >0: Amount of dirt on space
-     *  0: Empty
-     * -1: Charging pad
-     * -2: Obstacle
-     * -3: Hazard */
-    board: Vec<i32>,
-    xsize: i32,
-    ysize: i32,
+/* Direction the robot is facing. The diagonal headings are only used by
+ * robots that can drive diagonally. */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Heading {
+    Up,
+    UpRight,
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+    UpLeft,
+}
+
+const HEADINGS: [Heading; 8] = [
+    Heading::Up, Heading::UpRight, Heading::Right, Heading::DownRight,
+    Heading::Down, Heading::DownLeft, Heading::Left, Heading::UpLeft,
+];
+
+impl Heading {
+    /* Turned clockwise by a number of eighths of a circle (negative turns left) */
+    pub fn turn(self, eighths: i32) -> Heading {
+        HEADINGS[(self as i32 + eighths).rem_euclid(8) as usize]
+    }
+    pub fn is_diagonal(self) -> bool {
+        self as i32 % 2 == 1
+    }
+    /* One square forwards */
+    pub fn step(self) -> (i32, i32) {
+        match self {
+            Heading::Up => (0, -1),
+            Heading::UpRight => (1, -1),
+            Heading::Right => (1, 0),
+            Heading::DownRight => (1, 1),
+            Heading::Down => (0, 1),
+            Heading::DownLeft => (-1, 1),
+            Heading::Left => (-1, 0),
+            Heading::UpLeft => (-1, -1),
+        }
+    }
+    /* Value of the heading in the network input, in quarter turns from up */
+    pub fn value(self) -> f32 {
+        self as i32 as f32 / 2.0
+    }
+    /* Turns an offset given for a robot facing up (or up and right, for
+     * diagonal headings) to this heading */
+    fn rotate(self, (u, v): (i32, i32)) -> (i32, i32) {
+        match self as i32 / 2 {
+            0 => (u, v),
+            1 => (-v, u),
+            2 => (-u, -v),
+            _ => (v, -u),
+        }
+    }
+}
+
+/* Shape of a robot: a square body of size x size squares with its two front
+ * corners rounded off, and a suction head suction_width squares wide across
+ * the front, reaching back into the rounded corners. A diagonal robot can
+ * also face the four diagonals, with the corner it faces rounded off and a
+ * suction head running diagonally across it.
+ * A continuous robot instead moves freely with differential drive, as a disc
+ * of the same size with a straight suction head across its front; the grid
+ * pose is then the nearest one to it.
+ * Its height, in cm, decides which furniture it fits under, and its dust
+ * bin holds bin_capacity units of dirt (0: it never fills). */
+#[derive(Clone)]
+pub struct RobotModel {
+    pub size: i32,
+    pub suction_width: i32,
+    pub diagonal: bool,
+    pub continuous: bool,
+    pub height: i32,
+    pub bin_capacity: i32,
+}
+
+impl Default for RobotModel {
+    fn default() -> RobotModel {
+        RobotModel{size: 4, suction_width: 4, diagonal: false, continuous: false, height: 9, bin_capacity: 0}
+    }
+}
+
+impl RobotModel {
+    /* The body and suction head of a robot facing up, as offsets from the
+     * middle of the body in half squares, so that they rotate exactly.
+     * Each list runs from left to right as the robot sees it. */
+    fn body(&self) -> Vec<(i32, i32)> {
+        let r = self.size - 1;
+        let mut body = Vec::new();
+        for v in (-r..=r).step_by(2) {
+            for u in (-r..=r).step_by(2) {
+                if v != -r || u.abs() != r {
+                    body.push((u, v));
+                }
+            }
+        }
+        body
+    }
+    fn suction(&self) -> Vec<(i32, i32)> {
+        let (r, w) = (self.size - 1, self.suction_width - 1);
+        (-w..=w).step_by(2)
+            .map(|u| if u.abs() == r {(u, -r)} else {(u, -r - 2)})
+            .collect()
+    }
+    /* The same, facing up and right. The head covers an odd number of squares
+     * along the diagonal through the rounded corner. */
+    fn diagonal_body(&self) -> Vec<(i32, i32)> {
+        let r = self.size - 1;
+        let mut body = Vec::new();
+        for v in (-r..=r).step_by(2) {
+            for u in (-r..=r).step_by(2) {
+                if v != -r || u != r {
+                    body.push((u, v));
+                }
+            }
+        }
+        body
+    }
+    fn diagonal_suction(&self) -> Vec<(i32, i32)> {
+        let (r, m) = (self.size - 1, (self.suction_width - 1) / 2);
+        (-m..=m).map(|k| (r + 2 * k, -r + 2 * k)).collect()
+    }
+    /* Eighths of a circle turned by L and R */
+    fn turn(&self) -> i32 {
+        if self.diagonal {1} else {2}
+    }
+    /* Continuous robots: radius of the body, a little inside the square it
+     * fills on the grid so that it can pass through gaps of its size, and
+     * distance between the wheels */
+    fn radius(&self) -> f32 {
+        self.size as f32 / 2.0 - 0.1
+    }
+    fn wheelbase(&self) -> f32 {
+        self.size as f32
+    }
+}
+
+/* Actuation noise, as the chance per action of each kind of error: a move
+ * failing as the wheels slip, overshooting by another square or drifting a
+ * square sideways, and a turn going a step too far or not happening at all.
+ * Continuous robots make the same errors by half as much, see noisy_drive().
+ * All zero: the robot does exactly as it's told. */
+#[derive(Clone, Copy, Default)]
+pub struct Noise {
+    pub slip: f32,
+    pub overshoot: f32,
+    pub drift: f32,
+    pub turn_error: f32,
+}
 
+/* Parameters for random level generation, as counts per square of the room,
+ * and for how dirt builds up while the robot is cleaning. */
+#[derive(Clone)]
+pub struct LevelConfig {
+    pub hazard_density: f32,
+    pub obstacle_density: f32,
+    pub dirt_density: f32,
+    /* Dirt is spread uniformly, or if dirt_clusters > 0, gathers around that
+     * many hotspots (think kitchens and doorways) with a Gaussian spread of
+     * dirt_spread squares. */
+    pub dirt_clusters: usize,
+    pub dirt_spread: f32,
+    /* Dirt that settles per square per step, following the same hotspots */
+    pub dirt_regrowth: f32,
+    /* Chance per step of a spill, dropping a patch of dirt somewhere */
+    pub spill_rate: f32,
+    /* Carpeted areas and rugs, as counts per square like hazards */
+    pub carpet_density: f32,
+    pub rug_density: f32,
+    /* The robots that clean the room, and the charge each action takes
+     * out of their batteries (0: batteries never run down), more for
+     * driving onto rugs and carpet */
+    pub robot: RobotModel,
+    pub robots: usize,
+    pub battery_drain: f32,
+    pub noise: Noise,
+    /* Obstacles that move about by themselves, by how they move, and their
+     * size in squares */
+    pub wanderers: usize,
+    pub patrollers: usize,
+    pub followers: usize,
+    pub mover_size: i32,
+    /* Rooms the level is divided into by walls, and furnished, see
+     * floorplan.rs (0: one open room with only the obstacles above) */
+    pub rooms: usize,
+    /* Free-standing thin walls, as counts per square like obstacles */
+    pub wall_density: f32,
+    /* Stairs going down from the edges of the room, cords and liquid spills,
+     * as counts per square like hazards */
+    pub cliff_density: f32,
+    pub cord_density: f32,
+    pub liquid_density: f32,
+    /* Areas the owner has told the robot to keep out of, and virtual walls
+     * (one square wide) it mustn't cross. The robot is penalised for being in
+     * a no-go zone, or if block_no_go, can't enter one at all. */
+    pub no_go: Vec<Rect>,
+    pub virtual_walls: Vec<Rect>,
+    pub block_no_go: bool,
+    /* Named areas of the room, such as the kitchen, and what the robot is
+     * asked to do, see mission.rs */
+    pub zones: Vec<(String, Rect)>,
+    pub mission: Mission,
+}
+
+impl Default for LevelConfig {
+    fn default() -> LevelConfig {
+        LevelConfig{hazard_density: 1.0 / 800.0, obstacle_density: 1.0 / 200.0, dirt_density: 1.0 / 10.0,
+                    dirt_clusters: 0, dirt_spread: 4.0, dirt_regrowth: 0.0, spill_rate: 0.0,
+                    carpet_density: 0.0, rug_density: 0.0, robot: RobotModel::default(),
+                    robots: 1, battery_drain: 0.0, noise: Noise::default(),
+                    wanderers: 0, patrollers: 0, followers: 0, mover_size: 2, rooms: 0,
+                    wall_density: 0.0, cliff_density: 0.0, cord_density: 0.0, liquid_density: 0.0,
+                    no_go: Vec::new(), virtual_walls: Vec::new(), block_no_go: false,
+                    zones: Vec::new(), mission: Mission::default()}
+    }
+}
+
+impl LevelConfig {
+    /* Level options, each defaulting to the value above:
+     *   --hazard-density, --obstacle-density, --dirt-density <per square>
+     *   --dirt-clusters <N>, --dirt-spread <squares>
+     *   --dirt-regrowth <per square per step>, --spill-rate <per step>
+     *   --carpet-density, --rug-density <per square>
+     *   --robot-size <squares> (3 to 8), --suction-width <squares> (up to the
+     *     robot size, and odd or even with it), --robot-height <cm>
+     *   --bin-capacity <units of dirt>
+     *   --diagonal                 Robot can turn to and drive along diagonals
+     *   --continuous               Robot moves freely rather than on the grid
+     *   --robots <N>, --battery-drain <per action>
+     *   --slip, --overshoot, --drift, --turn-error <chance per action>
+     *   --wanderers, --patrollers, --followers <N>, --mover-size <squares>
+     *   --rooms <N>, --wall-density <per square>
+     *   --cliff-density, --cord-density, --liquid-density <per square>
+     *   --no-go, --virtual-walls <x0,y0,x1,y1/...>
+     *                              Rectangles, corners included; walls must
+     *                              be a single row or column
+     *   --block-no-go              Robot can't enter no-go zones
+     *   --zones <name:x0,y0,x1,y1/...>
+     * plus the mission options of Mission. */
+    pub fn from_options(opts: &Options) -> LevelConfig {
+        let d = LevelConfig::default();
+        let walls = parse_rects(opts.get_str("virtual-walls").unwrap_or(""), "virtual-walls");
+        let zones: Vec<(String, Rect)> = opts.get_str("zones").unwrap_or("").split('/').filter(|z| !z.is_empty())
+            .map(|z| {
+                let (name, rect) = z.split_once(':')
+                    .unwrap_or_else(|| panic!("Invalid zone '{}' for --zones, expected name:x0,y0,x1,y1", z));
+                (name.to_string(), parse_rects(rect, "zones")[0])
+            })
+            .collect();
+        let mission = Mission::from_options(opts);
+        if let Area::Zone(name) = &mission.area {
+            if !zones.iter().any(|(n, _)| n == name) {
+                panic!("No zone named '{}' in --zones", name);
+            }
+        }
+        if walls.iter().any(|r| r.x1 - r.x0 > 1 && r.y1 - r.y0 > 1) {
+            panic!("Virtual walls must be a single row or column of squares");
+        }
+        let robot = RobotModel{
+            size: opts.get("robot-size", d.robot.size),
+            suction_width: opts.get("suction-width", d.robot.suction_width),
+            diagonal: opts.has("diagonal"),
+            continuous: opts.has("continuous"),
+            height: opts.get("robot-height", d.robot.height),
+            bin_capacity: opts.get("bin-capacity", d.robot.bin_capacity).max(0),
+        };
+        if !(3..=8).contains(&robot.size) {
+            panic!("Robot size must be between 3 and 8, not {}", robot.size);
+        }
+        if robot.suction_width < 1 || robot.suction_width > robot.size || (robot.size - robot.suction_width) % 2 != 0 {
+            panic!("Suction width {} doesn't fit a robot of size {}", robot.suction_width, robot.size);
+        }
+        LevelConfig{
+            hazard_density: opts.get("hazard-density", d.hazard_density),
+            obstacle_density: opts.get("obstacle-density", d.obstacle_density),
+            dirt_density: opts.get("dirt-density", d.dirt_density),
+            dirt_clusters: opts.get("dirt-clusters", d.dirt_clusters),
+            dirt_spread: opts.get("dirt-spread", d.dirt_spread),
+            dirt_regrowth: opts.get("dirt-regrowth", d.dirt_regrowth),
+            spill_rate: opts.get("spill-rate", d.spill_rate),
+            carpet_density: opts.get("carpet-density", d.carpet_density),
+            rug_density: opts.get("rug-density", d.rug_density),
+            robot,
+            robots: opts.get("robots", d.robots).max(1),
+            battery_drain: opts.get("battery-drain", d.battery_drain),
+            noise: Noise{
+                slip: opts.get("slip", d.noise.slip),
+                overshoot: opts.get("overshoot", d.noise.overshoot),
+                drift: opts.get("drift", d.noise.drift),
+                turn_error: opts.get("turn-error", d.noise.turn_error),
+            },
+            wanderers: opts.get("wanderers", d.wanderers),
+            patrollers: opts.get("patrollers", d.patrollers),
+            followers: opts.get("followers", d.followers),
+            mover_size: opts.get("mover-size", d.mover_size).max(1),
+            rooms: opts.get("rooms", d.rooms),
+            wall_density: opts.get("wall-density", d.wall_density),
+            cliff_density: opts.get("cliff-density", d.cliff_density),
+            cord_density: opts.get("cord-density", d.cord_density),
+            liquid_density: opts.get("liquid-density", d.liquid_density),
+            no_go: parse_rects(opts.get_str("no-go").unwrap_or(""), "no-go"),
+            virtual_walls: walls,
+            block_no_go: opts.has("block-no-go"),
+            zones,
+            mission,
+        }
+    }
+    /* The options that from_options() reads back into this configuration */
+    pub fn to_options(&self) -> Vec<(String, String)> {
+        let mut opts: Vec<(String, String)> = [("hazard-density", self.hazard_density.to_string()),
+         ("obstacle-density", self.obstacle_density.to_string()),
+         ("dirt-density", self.dirt_density.to_string()),
+         ("dirt-clusters", self.dirt_clusters.to_string()),
+         ("dirt-spread", self.dirt_spread.to_string()),
+         ("dirt-regrowth", self.dirt_regrowth.to_string()),
+         ("spill-rate", self.spill_rate.to_string()),
+         ("carpet-density", self.carpet_density.to_string()),
+         ("rug-density", self.rug_density.to_string()),
+         ("robot-size", self.robot.size.to_string()),
+         ("suction-width", self.robot.suction_width.to_string()),
+         ("robot-height", self.robot.height.to_string()),
+         ("bin-capacity", self.robot.bin_capacity.to_string()),
+         ("robots", self.robots.to_string()),
+         ("battery-drain", self.battery_drain.to_string()),
+         ("slip", self.noise.slip.to_string()),
+         ("overshoot", self.noise.overshoot.to_string()),
+         ("drift", self.noise.drift.to_string()),
+         ("turn-error", self.noise.turn_error.to_string()),
+         ("wanderers", self.wanderers.to_string()),
+         ("patrollers", self.patrollers.to_string()),
+         ("followers", self.followers.to_string()),
+         ("mover-size", self.mover_size.to_string()),
+         ("rooms", self.rooms.to_string()),
+         ("wall-density", self.wall_density.to_string()),
+         ("cliff-density", self.cliff_density.to_string()),
+         ("cord-density", self.cord_density.to_string()),
+         ("liquid-density", self.liquid_density.to_string())]
+            .into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+        if !self.no_go.is_empty() {
+            opts.push(("no-go".to_string(), format_rects(&self.no_go)));
+        }
+        if !self.virtual_walls.is_empty() {
+            opts.push(("virtual-walls".to_string(), format_rects(&self.virtual_walls)));
+        }
+        if self.block_no_go {
+            opts.push(("block-no-go".to_string(), String::new()));
+        }
+        if !self.zones.is_empty() {
+            let zones: Vec<String> = self.zones.iter().map(|(name, r)| format!("{}:{}", name, format_rects(&[*r]))).collect();
+            opts.push(("zones".to_string(), zones.join("/")));
+        }
+        opts.extend(self.mission.to_options());
+        if self.robot.diagonal {
+            opts.push(("diagonal".to_string(), String::new()));
+        }
+        if self.robot.continuous {
+            opts.push(("continuous".to_string(), String::new()));
+        }
+        opts
+    }
+}
+
+/* Rectangles given as x0,y0,x1,y1 with both corners included, separated by
+ * '/' */
+fn parse_rects(text: &str, name: &str) -> Vec<Rect> {
+    text.split('/').filter(|r| !r.is_empty()).map(|r| {
+        let v: Vec<i32> = r.split(',').map(|n| n.trim().parse().ok()).collect::<Option<_>>()
+            .filter(|v: &Vec<i32>| v.len() == 4)
+            .unwrap_or_else(|| panic!("Invalid rectangle '{}' for --{}, expected x0,y0,x1,y1", r, name));
+        Rect{x0: v[0].min(v[2]), y0: v[1].min(v[3]), x1: v[0].max(v[2]) + 1, y1: v[1].max(v[3]) + 1}
+    }).collect()
+}
+fn format_rects(rects: &[Rect]) -> String {
+    rects.iter().map(|r| format!("{},{},{},{}", r.x0, r.y0, r.x1 - 1, r.y1 - 1)).collect::<Vec<_>>().join("/")
+}
+
+/* Floor covering of a square. Carpet and rugs hold on to dirt, needing
+ * several passes of the suction head per unit, and are slower to drive on. */
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Floor {
+    Hard,
+    Rug,
+    Carpet,
+}
+
+impl Floor {
+    /* SUCK passes needed to lift one unit of dirt */
+    pub fn passes(self) -> i32 {
+        match self {
+            Floor::Hard => 1,
+            Floor::Rug => 2,
+            Floor::Carpet => 3,
+        }
+    }
+    /* Extra time (reward penalty) for each move onto the floor */
+    pub fn drive_cost(self) -> f32 {
+        match self {
+            Floor::Hard => 0.0,
+            Floor::Rug => 0.02,
+            Floor::Carpet => 0.05,
+        }
+    }
+}
+
+/* What is on a square of the board */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Cell {
+    Floor(i32), /* Open floor, with an amount of dirt */
+    Pad,        /* Charging pad */
+    Obstacle,
+    Hazard,     /* Something fragile, which the robot mustn't touch */
+    Cliff,      /* The top of a flight of stairs */
+    Cord,       /* A cable lying on the floor */
+    Liquid,     /* A wet spill */
+}
+
+impl Cell {
+    /* Amount of dirt on the square */
+    pub fn dirt(self) -> i32 {
+        match self {
+            Cell::Floor(d) => d,
+            _ => 0,
+        }
+    }
+    /* Open floor, where dirt can settle and the robot cleans */
+    pub fn is_floor(self) -> bool {
+        matches!(self, Cell::Floor(_))
+    }
+    /* Value of the square in the network input: the amount of dirt on
+     * floor, -1 for the pad, -2 for obstacles, -3 for hazards, -4 for cliffs,
+     * -5 for cords and -6 for liquid */
+    pub fn code(self) -> i32 {
+        match self {
+            Cell::Floor(d) => d,
+            Cell::Pad => -1,
+            Cell::Obstacle => -2,
+            Cell::Hazard => -3,
+            Cell::Cliff => -4,
+            Cell::Cord => -5,
+            Cell::Liquid => -6,
+        }
+    }
+}
+
+/* Driving onto a cliff, the robot falls and the episode is over. A cord
+ * entangles it, so that it can't do anything for a few steps. Liquid wets its
+ * wheels, and it spreads the spill onto the floor it leaves for a few moves
+ * after, at a cost for each square. */
+const CLIFF_PENALTY: f32 = -100.0;
+const CORD_PENALTY: f32 = -5.0;
+const CORD_STEPS: i32 = 4;
+const LIQUID_PENALTY: f32 = -2.0;
+const SPREAD_PENALTY: f32 = -1.0;
+const WET_MOVES: i32 = 3;
+
+/* Penalty per action for being in a no-go zone */
+const NO_GO_PENALTY: f32 = -2.0;
+
+/* Reward for completing a mission */
+const MISSION_BONUS: f32 = 50.0;
+
+/* Fraction of the dust bin filled beyond which suction starts to suffer */
+const BIN_NEAR_FULL: f32 = 0.8;
+
+/* Penalty for trying to dock away from the pad or facing the wrong way */
+const MISSED_DOCK_PENALTY: f32 = -1.0;
+
+/* Squares the owner has told the robot to keep out of. The level generator
+ * knows nothing about them. */
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Zone {
+    Open,
+    NoGo,
+    Wall,
+}
+
+/* One square of the board */
+#[derive(Clone, Copy)]
+struct Square {
+    cell: Cell,
+    floor: Floor,
+    /* Suction passes made so far on the top unit of dirt */
+    passes: i32,
+    /* Height of the gap under furniture over the square, in cm (0: nothing
+     * overhead), see floorplan.rs */
+    clearance: i32,
+    zone: Zone,
+    /* Liquid tracked over the floor by wet wheels, rather than a spill */
+    trail: bool,
+}
+
+const EMPTY: Square = Square{cell: Cell::Floor(0), floor: Floor::Hard, passes: 0, clearance: 0, zone: Zone::Open,
+                             trail: false};
+
+impl Square {
+    /* Drops dirt on the square if it's floor, returning whether it was */
+    fn add_dirt(&mut self, d: i32) -> bool {
+        match self.cell {
+            Cell::Floor(old) => {
+                self.cell = Cell::Floor(old + d);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/* State of one robot in a room */
+#[derive(Clone)]
+struct Robot {
     /* Robot position and heading:
-     * Robots occupy a 4x4 space. Origin is at 1, 1. */
+     * Robots occupy a size x size space. Origin is at 1, 1. */
     x: i32,
     y: i32,
-    dirn: i32, /* 0: UP, 1: RIGHT, 2: DOWN, 3: LEFT */
-
+    heading: Heading,
+    /* Exact pose of a continuous robot, which x, y and heading follow */
+    pose: Option<Pose>,
+    /* Charge left, 1 when full, and dirt in the dust bin */
+    battery: f32,
+    bin: i32,
+    /* Whether it's docked on the charging pad, see dock() */
+    docked: bool,
+    /* Hazards: whether it has fallen down a cliff, steps left entangled in
+     * a cord, and moves left spreading liquid */
+    fallen: bool,
+    stuck: i32,
+    wet: i32,
     /* Cumulative reward */
     r: f32,
 }
 
-pub const SIZE_STATE: usize = 403;
-pub const SIZE_ACTION: usize = 5;
-pub type RoomVec = [f32; SIZE_STATE as usize];
+pub struct Room {
+    /* Game board, stored in an array (xsize * ysize) in length */
+    board: Vec<Square>,
+    xsize: i32,
+    ysize: i32,
+
+    /* The robot being moved. In rooms with several robots, the rest wait in
+     * `others` and take turns in `robot`, see step(). Robot 0 is the one
+     * moved by perform_action(). */
+    robot: Robot,
+    others: Vec<Robot>,
+    /* The robots' shape facing up and facing up-right, see RobotModel */
+    body: [Vec<(i32, i32)>; 2],
+    suction: [Vec<(i32, i32)>; 2],
+
+    /* Level generation: every room is reproducible from its seed. Rooms
+     * loaded from a map file keep a copy of the map, which replaces random
+     * generation whenever a new level is started. */
+    rng: StdRng,
+    seed: u64,
+    map: Option<Vec<Square>>,
+    config: LevelConfig,
+
+    /* Cleaning statistics: total dirt placed and removed over all levels
+     * played, and the squares swept by the robot on the current level. */
+    dirt_total: i32,
+    dirt_removed: i32,
+    visited: Vec<bool>,
+
+    /* Dirt dynamics: cumulative distribution of where dirt settles over the
+     * squares of the board, and the fraction of a unit of dirt that has
+     * settled but not yet been placed. */
+    dirt_cdf: Vec<f32>,
+    regrowth: f32,
+
+    /* Squares changed away from the robot since the last draw */
+    changed: Vec<usize>,
+
+    /* Pets, people and anything else moving about, see dynamic.rs */
+    movers: Vec<Mover>,
+
+    /* Doorways of the floor plan, which random obstacles keep out of */
+    clear: Vec<Rect>,
+
+    /* Mission progress: steps taken this episode, whether the mission's
+     * area has been cleaned, and whether the mission is complete */
+    steps: usize,
+    area_clean: bool,
+    complete: bool,
+}
+
+pub const SIZE_STATE: usize = 812;
+pub const SIZE_ACTION: usize = 7;
+pub type RoomVec = [f32; SIZE_STATE];
 
 impl Room {
-    pub fn new(xsize: i32, ysize: i32) -> Room {
-        let board: Vec<i32> = vec![0; (xsize * ysize) as usize];
-        let mut room = Room{xsize, ysize, board, x: 1, y: ysize - 3, dirn: 0, r: 0.0};
+    /* Creates a room whose levels are generated deterministically from a seed
+     * and the generation parameters */
+    pub fn with_config(xsize: i32, ysize: i32, seed: u64, config: LevelConfig) -> Room {
+        let mut room = Room::empty(xsize, ysize, seed);
+        room.config = config;
+        room.place_robot();
         room.generate_level();
         room
     }
+    /* Loads a room from a map file. Each line of the file is a row of the
+     * board, with one character per square:
+     *  '.' or ' ': Empty
+     *  '1' to '9': Amount of dirt on space
+     *  'O':        Charging pad
+     *  'X':        Obstacle
+     *  '!':        Hazard
+     *  'V', '=', '~': Cliff, cord, liquid
+     *  'c', 'r':   Clean carpet, clean rug
+     *  'T', 'B', 'S': Clean floor under a table, a bed or a sofa
+     *  'N', '|':   Clean floor in a no-go zone, under a virtual wall
+     * Lines beginning with '#' are comments. Short rows are padded with empty
+     * squares. The robot starts at the bottom-left, as in generated levels. */
+    pub fn from_map(path: &str) -> io::Result<Room> {
+        Room::parse_map(&fs::read_to_string(path)?, path)
+    }
+    /* Parses the text of a map file, see from_map() */
+    pub fn parse_map(text: &str, path: &str) -> io::Result<Room> {
+        let rows: Vec<&str> = text.lines()
+            .filter(|line| !line.starts_with('#'))
+            .collect();
+        let ysize = rows.len() as i32;
+        let xsize = rows.iter().map(|row| row.chars().count()).max().unwrap_or(0) as i32;
+        if xsize < 8 || ysize < 8 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("{}: map must be at least 8x8", path)));
+        }
+        let mut map = vec![EMPTY; (xsize * ysize) as usize];
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                map[y * xsize as usize + x] = match c {
+                    '.' | ' ' => EMPTY,
+                    '1'..='9' => Square{cell: Cell::Floor(c as i32 - '0' as i32), ..EMPTY},
+                    'O' => Square{cell: Cell::Pad, ..EMPTY},
+                    'X' => Square{cell: Cell::Obstacle, ..EMPTY},
+                    '!' => Square{cell: Cell::Hazard, ..EMPTY},
+                    'V' => Square{cell: Cell::Cliff, ..EMPTY},
+                    '=' => Square{cell: Cell::Cord, ..EMPTY},
+                    '~' => Square{cell: Cell::Liquid, ..EMPTY},
+                    'c' => Square{floor: Floor::Carpet, ..EMPTY},
+                    'r' => Square{floor: Floor::Rug, ..EMPTY},
+                    'T' => Square{clearance: floorplan::TABLE, ..EMPTY},
+                    'B' => Square{clearance: floorplan::BED, ..EMPTY},
+                    'S' => Square{clearance: floorplan::SOFA, ..EMPTY},
+                    'N' => Square{zone: Zone::NoGo, ..EMPTY},
+                    '|' => Square{zone: Zone::Wall, ..EMPTY},
+                    _ => {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                                  format!("{}:{}: unknown square '{}'", path, y + 1, c)));
+                    }
+                };
+            }
+        }
+        let mut room = Room::empty(xsize, ysize, 0);
+        room.map = Some(map);
+        room.generate_level();
+        Ok(room)
+    }
+    fn empty(xsize: i32, ysize: i32, seed: u64) -> Room {
+        let board: Vec<Square> = vec![EMPTY; (xsize * ysize) as usize];
+        let visited = vec![false; (xsize * ysize) as usize];
+        let robot = RobotModel::default();
+        Room{xsize, ysize, board,
+             robot: Robot{x: 1, y: ysize - 3, heading: Heading::Up, pose: None, battery: 1.0, bin: 0,
+                          docked: false, fallen: false, stuck: 0, wet: 0, r: 0.0},
+             others: Vec::new(),
+             body: [robot.body(), robot.diagonal_body()],
+             suction: [robot.suction(), robot.diagonal_suction()],
+             rng: StdRng::seed_from_u64(seed), seed, map: None, config: LevelConfig::default(),
+             dirt_total: 0, dirt_removed: 0, visited,
+             dirt_cdf: Vec::new(), regrowth: 0.0, changed: Vec::new(),
+             movers: Vec::new(), clear: Vec::new(),
+             steps: 0, area_clean: false, complete: false}
+    }
     fn generate_level(&mut self) {
-        let mut rng = rand::thread_rng();
-        self.board.iter_mut().for_each(|x| *x = 0);
-        /* Charging station */
-        for x in 0..4 {
-            for y in 0..4 {
-                self.board[(((self.ysize - 4 + y) * self.xsize) + x) as usize] = -1;
+        self.visited.iter_mut().for_each(|v| *v = false);
+        self.regrowth = 0.0;
+        self.area_clean = false;
+        if let Some(map) = &self.map {
+            self.board.copy_from_slice(map);
+            self.compute_dirt_rates();
+        } else {
+            self.board.iter_mut().for_each(|x| *x = EMPTY);
+            /* Charging station, the size of the robot */
+            let size = self.config.robot.size;
+            for x in 0..size {
+                for y in 0..size {
+                    self.board[(((self.ysize - size + y) * self.xsize) + x) as usize].cell = Cell::Pad;
+                }
+            }
+            /* Walls and furniture */
+            self.clear.clear();
+            if self.config.rooms > 0 {
+                let plan = floorplan::generate(self.xsize, self.ysize, self.config.rooms,
+                                               self.config.robot.size + 2, self.near_start(), &mut self.rng);
+                for (square, (cell, clearance)) in self.board.iter_mut().zip(plan.cells.into_iter().zip(plan.clearance)) {
+                    if cell != Cell::Floor(0) {
+                        square.cell = cell;
+                    }
+                    square.clearance = clearance;
+                }
+                self.clear = plan.clear;
+            }
+            let plan: Vec<Cell> = self.board.iter().map(|sq| sq.cell).collect();
+            /* Generate room contents */
+            let area = (self.xsize * self.ysize) as f32;
+            for _ in 0..((area * self.config.carpet_density) as i32) {
+                let (xsize, ysize) = (self.rng.gen_range(8..24), self.rng.gen_range(6..16));
+                self.place_floor(Floor::Carpet, xsize, ysize);
+            }
+            for _ in 0..((area * self.config.rug_density) as i32) {
+                let (xsize, ysize) = (self.rng.gen_range(3..8), self.rng.gen_range(2..6));
+                self.place_floor(Floor::Rug, xsize, ysize);
+            }
+            for _ in 0..((area * self.config.hazard_density) as i32) {
+                let (xsize, ysize) = (self.rng.gen_range(4..12), self.rng.gen_range(4..12));
+                self.place_hazard(xsize, ysize);
+            }
+            for _ in 0..((area * self.config.obstacle_density) as i32) {
+                let size = self.rng.gen_range(4..12);
+                self.place_obstacle(size);
+            }
+            for _ in 0..((area * self.config.wall_density) as i32) {
+                let len = self.rng.gen_range(6..20);
+                self.place_wall(len);
+            }
+            for _ in 0..((area * self.config.cliff_density) as i32) {
+                let len = self.rng.gen_range(6..16);
+                self.place_cliff(len);
+            }
+            for _ in 0..((area * self.config.cord_density) as i32) {
+                let len = self.rng.gen_range(8..24);
+                self.place_cord(len);
+            }
+            for _ in 0..((area * self.config.liquid_density) as i32) {
+                let radius = self.rng.gen_range(1.0..3.0);
+                self.place_liquid(radius);
+            }
+            if self.config.rooms > 0 {
+                self.open_up(&plan);
+            }
+            self.compute_dirt_rates();
+            for _ in 0..((area * self.config.dirt_density) as i32) {
+                if self.config.dirt_clusters == 0 {
+                    self.place_dirt();
+                } else if let Some(i) = self.sample_dirt_square() {
+                    self.board[i].add_dirt(1);
+                }
             }
         }
-        /* Generate room contents */
-        for _ in 0..((self.xsize * self.ysize) / 800) {
-            self.place_hazard(rng.gen_range(4..12), rng.gen_range(4..12), &mut rng);
+        self.dirt_total += self.dirt_remaining();
+        self.place_zones();
+        self.place_others();
+        self.place_movers();
+    }
+    /* Marks the no-go zones and virtual walls from the config over the
+     * level, after it has been generated without them */
+    fn place_zones(&mut self) {
+        let zones = self.config.no_go.iter().map(|r| (r, Zone::NoGo))
+            .chain(self.config.virtual_walls.iter().map(|r| (r, Zone::Wall)));
+        for (r, zone) in zones {
+            for y in r.y0.max(0)..r.y1.min(self.ysize) {
+                for x in r.x0.max(0)..r.x1.min(self.xsize) {
+                    self.board[(y * self.xsize + x) as usize].zone = zone;
+                }
+            }
         }
-        for _ in 0..((self.xsize * self.ysize) / 200) {
-            self.place_obstacle(rng.gen_range(4..12), &mut rng);
+    }
+    fn place_dirt(&mut self) {
+        let x = self.rng.gen_range(0..self.xsize);
+        let y = self.rng.gen_range(0..self.ysize);
+        let i: usize = (y * self.xsize + x) as usize;
+        self.board[i].add_dirt(1);
+    }
+    fn compute_dirt_rates(&mut self) {
+        /* Relative rate at which dirt settles on each square: a little
+         * everywhere, plus a Gaussian bump around each hotspot. */
+        let mut rates = vec![1.0; self.board.len()];
+        if self.config.dirt_clusters > 0 {
+            let spread = 2.0 * self.config.dirt_spread * self.config.dirt_spread;
+            let hotspots: Vec<(f32, f32)> = (0..self.config.dirt_clusters)
+                .map(|_| (self.rng.gen_range(0.0..self.xsize as f32), self.rng.gen_range(0.0..self.ysize as f32)))
+                .collect();
+            for (i, rate) in rates.iter_mut().enumerate() {
+                let (x, y) = ((i as i32 % self.xsize) as f32, (i as i32 / self.xsize) as f32);
+                *rate = 0.05 + hotspots.iter()
+                    .map(|(hx, hy)| (-((x - hx) * (x - hx) + (y - hy) * (y - hy)) / spread).exp())
+                    .sum::<f32>();
+            }
         }
-        for _ in 0..((self.xsize * self.ysize) / 10) {
-            self.place_dirt(&mut rng);
+        let mut total = 0.0;
+        self.dirt_cdf = rates.iter().zip(self.board.iter()).map(|(rate, sq)| {
+            total += if sq.cell.is_floor() {*rate} else {0.0};
+            total
+        }).collect();
+    }
+    /* Picks a square of floor for dirt to land on, according to the dirt rates */
+    fn sample_dirt_square(&mut self) -> Option<usize> {
+        let total = *self.dirt_cdf.last()?;
+        if total <= 0.0 {
+            return None;
         }
+        let x = self.rng.gen_range(0.0..total);
+        let i = self.dirt_cdf.partition_point(|c| *c <= x).min(self.board.len() - 1);
+        if self.board[i].cell.is_floor() {Some(i)} else {None}
     }
-    fn place_dirt(&mut self, rng: &mut ThreadRng) {
-        let x = rng.gen_range(0..self.xsize);
-        let y = rng.gen_range(0..self.ysize);
-        let i: usize = (y * self.xsize + x) as usize;
-        if self.board[i] >= 0 {
-            self.board[i] += 1;
+    /* Lets dirt settle and spills happen, once per step */
+    fn update_dirt(&mut self) {
+        self.regrowth += self.config.dirt_regrowth * (self.xsize * self.ysize) as f32;
+        while self.regrowth >= 1.0 {
+            self.regrowth -= 1.0;
+            if let Some(i) = self.sample_dirt_square() {
+                self.board[i].add_dirt(1);
+                self.dirt_total += 1;
+                self.changed.push(i);
+            }
+        }
+        if self.config.spill_rate > 0.0 && self.rng.gen::<f32>() < self.config.spill_rate {
+            /* A spill covers a small patch around a random square */
+            let (cx, cy) = (self.rng.gen_range(0..self.xsize), self.rng.gen_range(0..self.ysize));
+            let radius = self.rng.gen_range(1..=2);
+            let robot = self.footprint();
+            for x in (cx - radius)..=(cx + radius) {
+                for y in (cy - radius)..=(cy + radius) {
+                    if x >= 0 && x < self.xsize && y >= 0 && y < self.ysize && !robot.contains(&(x, y)) {
+                        let i = (y * self.xsize + x) as usize;
+                        if self.board[i].cell.is_floor() {
+                            let d = self.rng.gen_range(1..=3);
+                            self.board[i].add_dirt(d);
+                            self.dirt_total += d;
+                            self.changed.push(i);
+                        }
+                    }
+                }
+            }
         }
     }
-    fn place_obstacle(&mut self, size: i32, rng: &mut ThreadRng) {
+    fn place_obstacle(&mut self, size: i32) {
         /* We're going to randomly generate a shape by growing it from the
          * middle. We begin with a core and keep track of its bounds. */
-        let xseed = rng.gen_range(0..self.xsize);
-        let yseed = rng.gen_range(0..self.ysize);
+        let xseed = self.rng.gen_range(0..self.xsize);
+        let yseed = self.rng.gen_range(0..self.ysize);
         let mut xmin: i32 = xseed;
         let mut xmax: i32 = xseed;
         let mut ymin: i32 = yseed;
         let mut ymax: i32 = yseed;
         let i = (yseed * self.xsize + xseed) as usize;
-        if self.board[i] == 0 {
-            self.board[i] = -2;
+        if self.board[i].cell == Cell::Floor(0) && !self.keep_clear(xseed, yseed) {
+            self.board[i].cell = Cell::Obstacle;
         } else {
             return;
         }
 
         /* And then we add adjacent cells, sliding them out in one of four directions. */
         for _ in 0..size {
-            let dirn = rng.gen_range(0..4);
+            let dirn = self.rng.gen_range(0..4);
             let dx: i32;
             let dy: i32;
             let startx: i32;
@@ -119,14 +914,14 @@ impl Room {
                 /* Even: Up or down */
                 dx = 0;
                 dy = if dirn == 0 {-1} else {1};
-                startx = rng.gen_range(xmin..=xmax);
+                startx = self.rng.gen_range(xmin..=xmax);
                 starty = yseed;
             } else {
                 /* Odd: Right or left */
                 dx = if dirn == 1 {1} else {-1};
                 dy = 0;
                 startx = xseed;
-                starty = rng.gen_range(ymin..=ymax);
+                starty = self.rng.gen_range(ymin..=ymax);
             }
             let mut x = startx;
             let mut y = starty;
@@ -138,110 +933,741 @@ impl Room {
                     /* Out of bounds, don't grow here */
                     break;
                 }
-                if x < 8 && y > self.ysize - 8 {
-                    /* Too close to charging station */
+                if self.keep_clear(x, y) {
+                    /* Too close to charging station, or in a doorway */
                     break;
                 }
                 let i = (y * self.xsize + x) as usize;
-                if self.board[i] == 0 {
-                    self.board[i] = -2;
+                if self.board[i].cell == Cell::Floor(0) {
+                    self.board[i].cell = Cell::Obstacle;
                     xmin = if x < xmin {x} else {xmin};
                     xmax = if x > xmax {x} else {xmax};
                     ymin = if y < ymin {y} else {ymin};
                     ymax = if y > ymax {y} else {ymax};
                     break;
-                } else if self.board[i] != -2 {
+                } else if self.board[i].cell != Cell::Obstacle {
                     break;
                 }
             }
         }
     }
-    fn place_hazard(&mut self, xsize: i32, ysize: i32, rng: &mut ThreadRng) {
+    fn place_wall(&mut self, len: i32) {
+        /* A thin straight wall, one square thick, sometimes with a turn at
+         * the end like a partition */
+        let (x0, y0) = (self.rng.gen_range(0..self.xsize), self.rng.gen_range(0..self.ysize));
+        let (dx, dy) = [(1, 0), (0, 1)][self.rng.gen_range(0..2)];
+        let turn = if self.rng.gen::<bool>() {self.rng.gen_range(3..8)} else {0};
+        let squares: Vec<(i32, i32)> = (0..len).map(|t| (x0 + dx * t, y0 + dy * t))
+            .chain((1..=turn).map(|t| (x0 + dx * (len - 1) + dy * t, y0 + dy * (len - 1) + dx * t)))
+            .collect();
+        /* Only across empty floor, away from the pad and doorways */
+        let fits = squares.iter().all(|(x, y)| {
+            self.cell(*x, *y) == Some(Cell::Floor(0)) && !self.keep_clear(*x, *y)
+        });
+        if fits {
+            for (x, y) in squares {
+                self.board[(y * self.xsize + x) as usize].cell = Cell::Obstacle;
+            }
+        }
+    }
+    /* Robot positions, by the square at the top-left of the body, where the
+     * body fits on the given cells */
+    fn body_fits(&self, cells: &[Cell]) -> Vec<bool> {
+        let size = self.config.robot.size;
+        (0..cells.len() as i32).map(|i| {
+            let (x0, y0) = (i % self.xsize, i / self.xsize);
+            x0 + size <= self.xsize && y0 + size <= self.ysize
+                && (y0..y0 + size).all(|y| (x0..x0 + size).all(|x| {
+                    matches!(cells[(y * self.xsize + x) as usize], Cell::Floor(_) | Cell::Pad) && !self.too_low(x, y)
+                }))
+        }).collect()
+    }
+    /* Breadth-first search from the positions in `from` over those where the
+     * body fits, giving the position each one was reached from */
+    fn search(&self, fits: &[bool], from: &[usize]) -> Vec<Option<usize>> {
+        let mut came = vec![None; fits.len()];
+        for &p in from {
+            came[p] = Some(p);
+        }
+        let mut queue: VecDeque<usize> = from.iter().copied().collect();
+        while let Some(p) = queue.pop_front() {
+            let (x, y) = (p as i32 % self.xsize, p as i32 / self.xsize);
+            for (x, y) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                if x >= 0 && x < self.xsize && y >= 0 && y < self.ysize {
+                    let q = (y * self.xsize + x) as usize;
+                    if fits[q] && came[q].is_none() {
+                        came[q] = Some(p);
+                        queue.push_back(q);
+                    }
+                }
+            }
+        }
+        came
+    }
+    /* Clears clutter out of the way wherever it cuts the robot off from floor
+     * it could get to with just the floor plan's `plan` cells, along the
+     * shortest way there, until nothing is cut off */
+    fn open_up(&mut self, plan: &[Cell]) {
+        let start = ((self.ysize - self.config.robot.size) * self.xsize) as usize;
+        let reachable = self.search(&self.body_fits(plan), &[start]);
+        let open: Vec<bool> = reachable.iter().map(Option::is_some).collect();
+        loop {
+            let cells: Vec<Cell> = self.board.iter().map(|sq| sq.cell).collect();
+            let fits = self.body_fits(&cells);
+            let reached = self.search(&fits, &[start]);
+            let mut p = match (0..fits.len()).find(|&p| fits[p] && open[p] && reached[p].is_none()) {
+                Some(p) => p,
+                None => return,
+            };
+            let from: Vec<usize> = (0..fits.len()).filter(|&p| reached[p].is_some()).collect();
+            let came = self.search(&open, &from);
+            let size = self.config.robot.size;
+            while reached[p].is_none() {
+                let (x0, y0) = (p as i32 % self.xsize, p as i32 / self.xsize);
+                for y in y0..y0 + size {
+                    for x in x0..x0 + size {
+                        let i = (y * self.xsize + x) as usize;
+                        self.board[i].cell = plan[i];
+                    }
+                }
+                p = came[p].expect("Cut off from the robot with the floor plan alone");
+            }
+        }
+    }
+    /* Whether a square is close to the charging pad or in a doorway, where
+     * nothing should get in the robot's way */
+    fn keep_clear(&self, x: i32, y: i32) -> bool {
+        self.start_area().contains(x, y) || self.clear.iter().any(|r| r.contains(x, y))
+    }
+    /* The corner around the charging pad that's kept clear, so that the
+     * robot can leave the pad and turn round: four squares more than the
+     * robot across, and three more up, above which its suction head sits */
+    fn start_area(&self) -> Rect {
+        let size = self.config.robot.size;
+        Rect{x0: 0, y0: self.ysize - size - 3, x1: size + 4, y1: self.ysize}
+    }
+    /* The start area and the row above it, which larger things stay out of */
+    fn near_start(&self) -> Rect {
+        let area = self.start_area();
+        Rect{y0: area.y0 - 1, ..area}
+    }
+    fn place_cliff(&mut self, len: i32) {
+        /* The top of some stairs, two squares deep along an edge of the room */
+        let (dx, dy, x0, y0) = match self.rng.gen_range(0..4) {
+            0 => (1, 0, self.rng.gen_range(0..self.xsize), 0),
+            1 => (1, 0, self.rng.gen_range(0..self.xsize), self.ysize - 2),
+            2 => (0, 1, 0, self.rng.gen_range(0..self.ysize)),
+            _ => (0, 1, self.xsize - 2, self.rng.gen_range(0..self.ysize)),
+        };
+        let squares: Vec<(i32, i32)> = (0..len).flat_map(|t| (0..2).map(move |d| (x0 + dx * t + dy * d, y0 + dy * t + dx * d)))
+            .filter(|(x, y)| *x < self.xsize && *y < self.ysize)
+            .collect();
+        if squares.iter().any(|(x, y)| self.keep_clear(*x, *y)) {
+            return;
+        }
+        for (x, y) in squares {
+            self.board[(y * self.xsize + x) as usize].cell = Cell::Cliff;
+        }
+    }
+    fn place_cord(&mut self, len: i32) {
+        /* A cable snaking across the floor, mostly in straight runs */
+        let (mut x, mut y) = (self.rng.gen_range(0..self.xsize), self.rng.gen_range(0..self.ysize));
+        let mut dirn = self.rng.gen_range(0..4);
+        for _ in 0..len {
+            if !self.cell(x, y).is_some_and(Cell::is_floor) || self.keep_clear(x, y) {
+                break;
+            }
+            self.board[(y * self.xsize + x) as usize].cell = Cell::Cord;
+            if self.rng.gen::<f32>() < 0.25 {
+                dirn = (dirn + if self.rng.gen::<bool>() {1} else {3}) % 4;
+            }
+            let (dx, dy) = [(0, -1), (1, 0), (0, 1), (-1, 0)][dirn];
+            (x, y) = (x + dx, y + dy);
+        }
+    }
+    fn place_liquid(&mut self, radius: f32) {
+        /* A roughly round puddle over open floor */
+        let (cx, cy) = (self.rng.gen_range(0..self.xsize), self.rng.gen_range(0..self.ysize));
+        let r = radius.ceil() as i32;
+        for y in (cy - r)..=(cy + r) {
+            for x in (cx - r)..=(cx + r) {
+                let inside = ((x - cx) * (x - cx) + (y - cy) * (y - cy)) as f32 <= radius * radius;
+                if inside && self.cell(x, y).is_some_and(Cell::is_floor) && !self.keep_clear(x, y) {
+                    self.board[(y * self.xsize + x) as usize].cell = Cell::Liquid;
+                }
+            }
+        }
+    }
+    fn place_floor(&mut self, floor: Floor, xsize: i32, ysize: i32) {
+        /* Lays a rectangle of floor covering, which may overlap anything */
+        let xmin = self.rng.gen_range(0..self.xsize);
+        let ymin = self.rng.gen_range(0..self.ysize);
+        for x in xmin..(xmin + xsize).min(self.xsize) {
+            for y in ymin..(ymin + ysize).min(self.ysize) {
+                self.board[(y * self.xsize + x) as usize].floor = floor;
+            }
+        }
+    }
+    fn place_hazard(&mut self, xsize: i32, ysize: i32) {
         /* Places a rectangualar obstacle of a specified size */
-        let xmin = rng.gen_range(0..self.xsize);
-        let ymin = rng.gen_range(0..self.ysize);
+        let xmin = self.rng.gen_range(0..self.xsize);
+        let ymin = self.rng.gen_range(0..self.ysize);
         let xmax = if xmin + xsize < self.xsize {xmin + xsize} else {self.xsize};
         let ymax = if ymin + ysize < self.ysize {ymin + ysize} else {self.ysize};
         /* Don't get too close to the charging pad */
-        if xmin < 8 && ymax > self.ysize - 8 {
+        let r = Rect{x0: xmin, y0: ymin, x1: xmax, y1: ymax};
+        if self.near_start().overlaps(&r) {
+            return;
+        }
+        /* Nor block a doorway */
+        if self.clear.iter().any(|c| c.overlaps(&r)) {
             return;
         }
         for x in xmin..xmax {
             for y in ymin..ymax {
-                self.board[(y * self.xsize + x) as usize] = -3;
-            }
-        }
-    }
-    fn get_suction_range(&self) -> [(i32, i32); 4] {
-        /* Returns the four squares immediately in front of the robot,
-         * In order from left-to-right relative to the robot's rotation.  */
-        match self.dirn {
-            0 => [(self.x-1, self.y-1), (self.x, self.y-2), (self.x+1, self.y-2), (self.x+2, self.y-1)],
-            1 => [(self.x+2, self.y-1), (self.x+3, self.y), (self.x+3, self.y+1), (self.x+2, self.y+2)],
-            2 => [(self.x+2, self.y+2), (self.x+1, self.y+3), (self.x, self.y+3), (self.x-1, self.y+2)],
-            3 => [(self.x-1, self.y+2), (self.x-2, self.y+1), (self.x-2, self.y), (self.x-1, self.y-1)],
-            _ => [(-1, -1), (-1, -1), (-1, -1), (-1, -1)],
-        }
-    }
-    fn get_occupied_squares(x: i32, y: i32, dirn: i32) -> [(i32, i32); 14] {
-        /* Given a (new) vaccuum position, return the squares that are covered by it. */
-        match dirn {
-            0 => [            (x, y-1), (x+1, y-1),
-                  (x-1,  y ), (x,  y ), (x+1,  y ), (x+2,  y ),
-                  (x-1, y+1), (x, y+1), (x+1, y+1), (x+2, y+1),
-                  (x-1, y+2), (x, y+2), (x+1, y+2), (x+2, y+2)],
-            1 => [(x-1, y-1), (x, y-1), (x+1, y-1),
-                  (x-1,  y ), (x,  y ), (x+1,  y ), (x+2,  y ),
-                  (x-1, y+1), (x, y+1), (x+1, y+1), (x+2, y+1),
-                  (x-1, y+2), (x, y+2), (x+1, y+2),           ],
-            2 => [(x-1, y-1), (x, y-1), (x+1, y-1), (x+2, y-1),
-                  (x-1,  y ), (x,  y ), (x+1,  y ), (x+2,  y ),
-                  (x-1, y+1), (x, y+1), (x+1, y+1), (x+2, y+1),
-                              (x, y+2), (x+1, y+2),           ],
-            3 => [            (x, y-1), (x+1, y-1), (x+2, y-1),
-                  (x-1,  y ), (x,  y ), (x+1,  y ), (x+2,  y ),
-                  (x-1, y+1), (x, y+1), (x+1, y+1), (x+2, y+1),
-                              (x, y+2), (x+1, y+2), (x+2, y+2)],
-            _ => [(-1, -1) ; 14],
+                self.board[(y * self.xsize + x) as usize].cell = Cell::Hazard;
+            }
         }
     }
+    /* Sets the robots up from the config, with robot 0 parked on the
+     * charging pad. The others are placed with the level. */
+    fn place_robot(&mut self) {
+        let robot = &self.config.robot;
+        self.body = [robot.body(), robot.diagonal_body()];
+        self.suction = [robot.suction(), robot.diagonal_suction()];
+        self.robot = self.parked();
+        self.others.clear();
+    }
+    /* A fresh robot on the charging pad */
+    fn parked(&self) -> Robot {
+        let (x, y) = self.start();
+        let pose = if self.config.robot.continuous {
+            let (x, y) = self.start_centre();
+            Some(Pose{x, y, theta: 0.0})
+        } else {
+            None
+        };
+        Robot{x, y, heading: Heading::Up, pose, battery: 1.0, bin: 0, docked: false, fallen: false, stuck: 0, wet: 0, r: 0.0}
+    }
+    /* Finds room for the other robots, moving any that a new level has put
+     * in the way of something. They line up from the bottom-left corner. */
+    fn place_others(&mut self) {
+        let n = self.config.robots.max(1) - 1;
+        let parked = self.parked();
+        self.others.resize(n, parked);
+        let half = self.config.robot.size as f32 / 2.0;
+        for i in 0..n {
+            if self.is_free(&self.others[i], i + 1) {
+                continue;
+            }
+            let (xstart, ystart) = self.start();
+            'search: for y in (0..=ystart).rev() {
+                for x in xstart..self.xsize {
+                    let mut b = self.others[i].clone();
+                    (b.x, b.y, b.heading) = (x, y, Heading::Up);
+                    if b.pose.is_some() {
+                        b.pose = Some(Pose{x: x as f32 - 1.0 + half, y: y as f32 - 1.0 + half, theta: 0.0});
+                    }
+                    if self.is_free(&b, i + 1) {
+                        self.others[i] = b;
+                        break 'search;
+                    }
+                }
+            }
+        }
+    }
+    /* Whether robot number i could be where b is: on floor or the pad, and
+     * clear of the other robots */
+    fn is_free(&self, b: &Robot, i: usize) -> bool {
+        let others = self.robot_squares_except(i);
+        self.robot_squares(b).iter().all(|(x, y)| {
+            matches!(self.cell(*x, *y), Some(Cell::Floor(_)) | Some(Cell::Pad)) && !others.contains(&(*x, *y))
+                && !self.too_low(*x, *y) && self.square(*x, *y).is_some_and(|sq| sq.zone == Zone::Open)
+        })
+    }
+    /* Scatters the movers over open floor, away from the pad and robots */
+    fn place_movers(&mut self) {
+        self.movers.clear();
+        let size = self.config.mover_size;
+        let motions = [(Motion::Wander, self.config.wanderers), (Motion::Patrol, self.config.patrollers),
+                       (Motion::Follow, self.config.followers)];
+        for (motion, n) in motions {
+            for _ in 0..n {
+                /* Give up on a mover if there's nowhere to put it */
+                for _ in 0..100 {
+                    let (x, y) = (self.rng.gen_range(0..self.xsize), self.rng.gen_range(0..self.ysize));
+                    let len = self.rng.gen_range(4..16) * if self.rng.gen() {1} else {-1};
+                    let end = if self.rng.gen() {
+                        ((x + len).clamp(0, self.xsize - size), y)
+                    } else {
+                        (x, (y + len).clamp(0, self.ysize - size))
+                    };
+                    let blocked = self.blocked_squares(usize::MAX);
+                    let near_pad = self.near_start().overlaps(&Rect{x0: x, y0: y, x1: x + size, y1: y + size});
+                    if !near_pad && self.mover_fits(size, x, y, &blocked) {
+                        self.movers.push(Mover::new(x, y, size, motion, end));
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    /* Whether a mover of the given size fits on open floor at (x, y) */
+    fn mover_fits(&self, size: i32, x: i32, y: i32, blocked: &[(i32, i32)]) -> bool {
+        /* Movers go round furniture rather than under it */
+        (0..size).all(|dy| (0..size).all(|dx| {
+            self.square(x + dx, y + dy).is_some_and(|sq| sq.cell.is_floor() && sq.clearance == 0)
+                && !blocked.contains(&(x + dx, y + dy))
+        }))
+    }
+    /* Moves the movers, each avoiding the robots and the others */
+    fn update_movers(&mut self) {
+        let goal = (self.robot.x, self.robot.y);
+        for i in 0..self.movers.len() {
+            let mut m = self.movers[i].clone();
+            let blocked: Vec<(i32, i32)> = self.blocked_squares(usize::MAX).into_iter()
+                .filter(|sq| !m.squares().contains(sq))
+                .collect();
+            if let Some((x, y)) = m.next(&mut self.rng, goal) {
+                m.advance((x, y), self.mover_fits(m.size, x, y, &blocked));
+            }
+            if (m.x, m.y) != (self.movers[i].x, self.movers[i].y) {
+                for (x, y) in self.movers[i].squares() {
+                    self.changed.push((y * self.xsize + x) as usize);
+                }
+                self.movers[i] = m;
+            }
+        }
+    }
+    /* Squares the robot numbered i can't go into: those covered by other
+     * robots and the movers */
+    fn blocked_squares(&self, i: usize) -> Vec<(i32, i32)> {
+        let mut blocked = self.robot_squares_except(i);
+        blocked.extend(self.movers.iter().flat_map(Mover::squares));
+        blocked
+    }
+    /* All the robots, robot 0 (or the one being moved) first */
+    fn robots(&self) -> impl Iterator<Item = &Robot> {
+        std::iter::once(&self.robot).chain(self.others.iter())
+    }
+    /* Squares covered by all the robots except number i */
+    fn robot_squares_except(&self, i: usize) -> Vec<(i32, i32)> {
+        self.robots().enumerate()
+            .filter(|(j, _)| *j != i)
+            .flat_map(|(_, b)| self.robot_squares(b))
+            .collect()
+    }
+    /* Origin of the robot when it's parked on the charging pad */
+    fn start(&self) -> (i32, i32) {
+        (1, self.ysize - self.config.robot.size + 1)
+    }
+    /* Middle of the robot's body, in squares, when it's on the pad */
+    fn start_centre(&self) -> (f32, f32) {
+        let (x, y) = self.start();
+        let half = self.config.robot.size as f32 / 2.0;
+        (x as f32 - 1.0 + half, y as f32 - 1.0 + half)
+    }
+    /* Squares covered by a robot's body, and under its suction head */
+    fn robot_squares(&self, b: &Robot) -> Vec<(i32, i32)> {
+        match b.pose {
+            Some(p) => continuous::circle_squares(p.x, p.y, self.config.robot.radius()),
+            None => self.shape_squares(&self.body, b.x, b.y, b.heading),
+        }
+    }
+    fn robot_suction(&self, b: &Robot) -> Vec<(i32, i32)> {
+        match b.pose {
+            Some(p) => p.head_squares(self.config.robot.radius(), self.config.robot.suction_width as f32),
+            None => self.shape_squares(&self.suction, b.x, b.y, b.heading),
+        }
+    }
+    /* Moves a continuous robot, and its grid pose to the nearest one */
+    fn set_pose(&mut self, pose: Pose) {
+        let half = self.config.robot.size as f32 / 2.0;
+        self.robot.pose = Some(pose);
+        self.robot.x = (pose.x - half).round() as i32 + 1;
+        self.robot.y = (pose.y - half).round() as i32 + 1;
+        self.robot.heading = pose.heading(self.config.robot.diagonal);
+    }
+    /* Squares of a shape (body or suction head) for a robot at the given pose */
+    fn shape_squares(&self, shape: &[Vec<(i32, i32)>; 2], x: i32, y: i32, heading: Heading) -> Vec<(i32, i32)> {
+        let r = self.config.robot.size - 1;
+        shape[heading.is_diagonal() as usize].iter().map(|p| {
+            let (u, v) = heading.rotate(*p);
+            (x - 1 + (u + r) / 2, y - 1 + (v + r) / 2)
+        }).collect()
+    }
+    /* For every square given, reduce dirt level by 1 (after enough passes on
+     * carpet), as long as there's room in the bin. Returns the amount of dirt
+     * removed within the mission's area, which is all that's rewarded. */
+    fn suck(&mut self, squares: &[(i32, i32)]) -> i32 {
+        let capacity = self.config.robot.bin_capacity;
+        let removed: Vec<&(i32, i32)> = squares.iter().filter(|(x, y)| {
+            if *x >= 0 && *x < self.xsize && *y >= 0 && *y < self.ysize {
+                self.visited[(y * self.xsize + x) as usize] = true;
+            }
+            if *x >= 0 && *x < self.xsize && *y >= 0 && *y < self.ysize
+                && self.board[(y * self.xsize + x) as usize].cell.dirt() > 0 {
+                let sq = &mut self.board[(y * self.xsize + x) as usize];
+                if sq.passes + 1 < sq.floor.passes() {
+                    sq.passes += 1;
+                    return false;
+                }
+                if capacity > 0 {
+                    /* Less gets picked up as the bin fills, and nothing once
+                     * it's full. Dirt that isn't picked up stays as loose as
+                     * it was. */
+                    let fill = self.robot.bin as f32 / capacity as f32;
+                    let efficiency = (1.0 - fill) / (1.0 - BIN_NEAR_FULL);
+                    if fill >= BIN_NEAR_FULL && self.rng.gen::<f32>() >= efficiency {
+                        return false;
+                    }
+                    self.robot.bin += 1;
+                }
+                sq.passes = 0;
+                sq.add_dirt(-1);
+                true
+            } else {
+                false
+            }
+        }).collect();
+        self.dirt_removed += removed.len() as i32;
+        removed.iter().filter(|(x, y)| self.config.mission.contains(&self.config.zones, *x, *y)).count() as i32
+    }
+    /* Checks on the mission after the robot being moved has acted, returning
+     * the bonus if that completed it */
+    fn mission_progress(&mut self) -> f32 {
+        if !self.config.mission.is_set() || self.complete {
+            return 0.0;
+        }
+        if !self.area_clean {
+            let mission = &self.config.mission;
+            let clean = self.cells().all(|(x, y, cell)| cell.dirt() == 0 || !mission.contains(&self.config.zones, x, y));
+            self.area_clean = clean;
+        }
+        if self.area_clean && (!self.config.mission.return_to_dock || self.robot.docked) {
+            self.complete = true;
+            MISSION_BONUS
+        } else {
+            0.0
+        }
+    }
+    /* Drives a continuous robot for one step. It stops short if it would hit
+     * an obstacle, hazard or robot, and cleans the squares its suction head
+     * sweeps over. Returns the reward, on the same scale as perform_action(). */
+    fn drive(&mut self, d: Drive) -> f32 {
+        let pose = self.robot.pose.expect("Only continuous robots can drive");
+        let robot = &self.config.robot;
+        let (radius, width, wheelbase) = (robot.radius(), robot.suction_width as f32, robot.wheelbase());
+        let (x, y) = (self.robot.x, self.robot.y);
+        let already = pose.head_squares(radius, width);
+        let (mut last, mut swept, mut penalty) = (pose, Vec::new(), 0.0);
+        for p in pose.drive(d, wheelbase) {
+            penalty = self.collision(&continuous::circle_squares(p.x, p.y, radius));
+            if penalty < 0.0 {
+                break;
+            }
+            for sq in p.head_squares(radius, width) {
+                if !already.contains(&sq) && !swept.contains(&sq) {
+                    swept.push(sq);
+                }
+            }
+            last = p;
+        }
+        /* Driving forwards is cheap, backwards and turning cost more, as on the grid */
+        let v = (d.left + d.right) / 2.0;
+        let turn = (d.left - d.right).abs() / wheelbase;
+        penalty -= if v >= 0.0 {0.05 * v} else {-v};
+        penalty -= 0.1 * turn / (std::f32::consts::PI / 2.0);
+        let before = self.footprint();
+        self.set_pose(last);
+        if (self.robot.x, self.robot.y) != (x, y) {
+            penalty -= self.drive_onto(self.robot.x, self.robot.y);
+        }
+        penalty + self.enter_hazards(&before) + self.suck(&swept) as f32
+    }
+    /* Penalty for the robot being moved covering the given squares: -50 for
+     * a hazard, -20 for an obstacle, a wall, a mover or another robot, 0 if
+     * clear */
+    fn collision(&self, squares: &[(i32, i32)]) -> f32 {
+        let others = self.blocked_squares(0);
+        let mut penalty = 0.0;
+        for (x, y) in squares {
+            match self.cell(*x, *y) {
+                Some(Cell::Hazard) => {
+                    penalty = if penalty > -50.0 { -50.0 } else { penalty };
+                }
+                Some(Cell::Obstacle) | None => {
+                    penalty = if penalty > -20.0 { -20.0 } else { penalty };
+                }
+                _ if others.contains(&(*x, *y)) || self.too_low(*x, *y) || self.fenced(*x, *y) => {
+                    penalty = if penalty > -20.0 { -20.0 } else { penalty };
+                }
+                _ => {}
+            }
+        }
+        penalty
+    }
+    /* Effects of the hazards the robot being moved has driven onto, given the
+     * squares it covered before. Returns the reward. */
+    fn enter_hazards(&mut self, before: &[(i32, i32)]) -> f32 {
+        let after = self.footprint();
+        let entered: Vec<Square> = after.iter().filter(|sq| !before.contains(sq))
+            .filter_map(|(x, y)| self.square(*x, *y).copied())
+            .collect();
+        let entered_any = |cell: Cell| entered.iter().any(|sq| sq.cell == cell && !sq.trail);
+        if entered_any(Cell::Cliff) {
+            self.robot.fallen = true;
+            return CLIFF_PENALTY;
+        }
+        let mut r = 0.0;
+        if entered_any(Cell::Cord) {
+            self.robot.stuck = CORD_STEPS;
+            r += CORD_PENALTY;
+        }
+        if entered_any(Cell::Liquid) {
+            self.robot.wet = WET_MOVES;
+            r += LIQUID_PENALTY;
+        } else if self.robot.wet > 0 && after != before {
+            /* Wet wheels leave a trail behind over clean floor. The trail is
+             * too thin to wet the wheels again, so a spill only spreads so
+             * far. */
+            self.robot.wet -= 1;
+            for (x, y) in before.iter().filter(|sq| !after.contains(sq)) {
+                if self.cell(*x, *y) == Some(Cell::Floor(0)) {
+                    let sq = &mut self.board[(y * self.xsize + x) as usize];
+                    sq.cell = Cell::Liquid;
+                    sq.trail = true;
+                    r += SPREAD_PENALTY;
+                }
+            }
+        }
+        r
+    }
+    /* Whether the episode is over, because a robot has fallen down a cliff,
+     * or the mission is complete or out of steps */
+    pub fn is_done(&self) -> bool {
+        let limit = self.config.mission.step_limit;
+        self.robots().any(|b| b.fallen) || self.complete || (limit > 0 && self.steps >= limit)
+    }
     /* Returns the reward from taking an action */
     pub fn perform_action(&mut self, a: Action) -> f32 {
+        self.steps += 1;
+        let r = self.act(a);
+        self.update_dirt();
+        self.update_movers();
+        r
+    }
+    /* Moves every robot in the room, robot i taking actions[i], and returns
+     * their rewards. The robots move in turn, so each sees where the ones
+     * before it went. */
+    pub fn step(&mut self, actions: &[Action]) -> Vec<f32> {
+        self.step_with(actions, Room::act)
+    }
+    /* Moves every robot of a continuous room by a differential drive
+     * command, robot i taking drives[i], and returns their rewards. The
+     * discrete actions are a wrapper on top of this: they drive a square
+     * forwards or backwards, or turn on the spot. The trainers here only use
+     * the actions. */
+    #[allow(dead_code)]
+    pub fn step_drive(&mut self, drives: &[Drive]) -> Vec<f32> {
+        assert!(self.config.robot.continuous, "Only continuous robots can drive");
+        self.step_with(drives, Room::act_drive)
+    }
+    /* Gives each robot in turn its command, then updates dirt and movers */
+    fn step_with<T: Copy>(&mut self, commands: &[T], act: fn(&mut Room, T) -> f32) -> Vec<f32> {
+        self.steps += 1;
+        let rewards = commands.iter().enumerate().map(|(i, c)| {
+            if i > 0 {
+                std::mem::swap(&mut self.robot, &mut self.others[i - 1]);
+            }
+            let r = act(self, *c);
+            if i > 0 {
+                std::mem::swap(&mut self.robot, &mut self.others[i - 1]);
+            }
+            r
+        }).collect();
+        self.update_dirt();
+        self.update_movers();
+        rewards
+    }
+    /* Docking and undocking. The robot docks by driving down onto the
+     * charging pad, so that it's on the pad facing the bottom wall, which
+     * recharges its battery and empties its bin. Once docked it waits there, doing nothing
+     * whatever it's told, until it undocks by turning round to face up. */
+    fn dock(&mut self, a: Action) -> f32 {
+        match (self.robot.docked, a) {
+            (false, Action::DOCK) => {
+                if (self.robot.x, self.robot.y) == self.start() && self.robot.heading == Heading::Down {
+                    self.robot.docked = true;
+                    self.robot.battery = 1.0;
+                    self.robot.bin = 0;
+                    0.0
+                } else {
+                    MISSED_DOCK_PENALTY
+                }
+            }
+            (true, Action::UNDOCK) => {
+                self.robot.docked = false;
+                if self.robot.pose.is_some() {
+                    let (x, y) = self.start_centre();
+                    self.set_pose(Pose{x, y, theta: 0.0});
+                } else {
+                    self.robot.heading = Heading::Up;
+                }
+                -0.1
+            }
+            (true, _) => 0.0,
+            (false, _) => -0.1,
+        }
+    }
+    /* Rolls for an error that happens with chance p, without touching the
+     * random numbers when it can't happen, so that levels stay the same */
+    fn chance(&mut self, p: f32) -> bool {
+        p > 0.0 && self.rng.gen::<f32>() < p
+    }
+    /* A continuous robot's drive command as it comes out: a slip loses it,
+     * an overshoot drives half as far again, a drift leaves one wheel half a
+     * square behind, and a turn on the spot has an error of half as much or
+     * half as much again */
+    fn noisy_drive(&mut self, d: Drive) -> Drive {
+        let noise = self.config.noise;
+        let scale = |d: Drive, k: f32| Drive{left: d.left * k, right: d.right * k};
+        if d.left == -d.right && d.left != 0.0 {
+            return if self.chance(noise.turn_error) {
+                scale(d, if self.rng.gen::<bool>() {0.5} else {1.5})
+            } else {
+                d
+            };
+        }
+        let mut d = if self.chance(noise.slip) {
+            scale(d, 0.0)
+        } else if self.chance(noise.overshoot) {
+            scale(d, 1.5)
+        } else {
+            d
+        };
+        if self.chance(noise.drift) {
+            if self.rng.gen::<bool>() {d.left -= 0.5 * d.left.signum()} else {d.right -= 0.5 * d.right.signum()}
+        }
+        d
+    }
+    /* The penalty for the robot being moved when it can't carry out a
+     * command: a flat battery, or struggling with a cord. Otherwise uses up
+     * some battery and returns None. */
+    fn powerless(&mut self) -> Option<f32> {
+        if self.robot.battery <= 0.0 {
+            /* A flat robot is stranded */
+            self.robot.r -= 0.1;
+            return Some(-0.1);
+        }
+        self.robot.battery -= self.config.battery_drain;
+        if self.robot.stuck > 0 {
+            /* Struggling with a cord */
+            self.robot.stuck -= 1;
+            self.robot.r -= 0.1;
+            return Some(-0.1);
+        }
+        None
+    }
+    /* The extra cost of the robot being moved driving onto the floor at
+     * (x, y). The extra time on carpet runs the battery down too, in
+     * proportion to a move forward's 0.05. */
+    fn drive_onto(&mut self, x: i32, y: i32) -> f32 {
+        let cost = self.floor(x, y).map_or(0.0, Floor::drive_cost);
+        self.robot.battery -= self.config.battery_drain * cost / 0.05;
+        cost
+    }
+    /* Performs a drive command with the continuous robot being moved, before
+     * dirt is updated */
+    fn act_drive(&mut self, d: Drive) -> f32 {
+        if self.robot.fallen {
+            return 0.0;
+        }
+        let r = if self.robot.docked {
+            /* Waits on the pad until it undocks */
+            self.mission_progress()
+        } else if let Some(r) = self.powerless() {
+            return r;
+        } else {
+            let d = self.noisy_drive(d);
+            self.drive(d) + self.trespass() + self.mission_progress()
+        };
+        self.robot.r += r;
+        r
+    }
+    /* Performs an action with the robot being moved, before dirt is updated */
+    fn act(&mut self, a: Action) -> f32 {
+        if self.robot.pose.is_some() && !self.robot.docked
+            && matches!(a, Action::FORWARD | Action::REVERSE | Action::L | Action::R) {
+            /* Continuous robots drive a square, or turn on the spot */
+            let angle = self.config.robot.turn() as f32 * std::f32::consts::PI / 4.0;
+            let wheelbase = self.config.robot.wheelbase();
+            return self.act_drive(match a {
+                Action::FORWARD => Drive::straight(1.0),
+                Action::REVERSE => Drive::straight(-1.0),
+                Action::L => Drive::spin(-angle, wheelbase),
+                _ => Drive::spin(angle, wheelbase),
+            });
+        }
+        if self.robot.fallen {
+            return 0.0;
+        }
+        if self.robot.docked || a == Action::DOCK || a == Action::UNDOCK {
+            let r = self.dock(a);
+            let r = r + self.mission_progress();
+            self.robot.r += r;
+            return r;
+        }
+        if let Some(r) = self.powerless() {
+            return r;
+        }
         /* Calculate new positions */
-        let (mut nx, mut ny, mut ndirn) = match a {
-            Action::FORWARD => match self.dirn {
-                0 => (self.x,     self.y - 1, self.dirn),
-                1 => (self.x + 1, self.y,     self.dirn),
-                2 => (self.x,     self.y + 1, self.dirn),
-                3 => (self.x - 1, self.y,     self.dirn),
-                _ => (self.x,     self.y,     self.dirn),
-            },
-            Action::REVERSE => match self.dirn {
-                0 => (self.x,     self.y + 1, self.dirn),
-                1 => (self.x - 1, self.y,     self.dirn),
-                2 => (self.x,     self.y - 1, self.dirn),
-                3 => (self.x + 1, self.y,     self.dirn),
-                _ => (self.x,     self.y,     self.dirn),
-            },
-            Action::L => (self.x, self.y, (self.dirn - 1) & 0x3),
-            Action::R => (self.x, self.y, (self.dirn + 1) & 0x3),
-            Action::SUCK => (self.x, self.y, self.dirn),
+        let (dx, dy) = self.robot.heading.step();
+        let (mut nx, mut ny, mut nheading) = match a {
+            Action::FORWARD => (self.robot.x + dx, self.robot.y + dy, self.robot.heading),
+            Action::REVERSE => (self.robot.x - dx, self.robot.y - dy, self.robot.heading),
+            Action::L => (self.robot.x, self.robot.y, self.robot.heading.turn(-self.config.robot.turn())),
+            Action::R => (self.robot.x, self.robot.y, self.robot.heading.turn(self.config.robot.turn())),
+            _ => (self.robot.x, self.robot.y, self.robot.heading),
         };
+        /* Squares the robot passes through on the way, when it doesn't move
+         * as it was told */
+        let mut via = Vec::new();
+        let noise = self.config.noise;
+        if a == Action::FORWARD || a == Action::REVERSE {
+            let (sx, sy) = (nx - self.robot.x, ny - self.robot.y);
+            if self.chance(noise.slip) {
+                (nx, ny) = (self.robot.x, self.robot.y);
+            } else if self.chance(noise.overshoot) {
+                via.push((nx, ny));
+                (nx, ny) = (nx + sx, ny + sy);
+            }
+            if self.chance(noise.drift) {
+                let (ux, uy) = self.robot.heading.turn(if self.rng.gen::<bool>() {2} else {-2}).step();
+                via.push((nx, ny));
+                (nx, ny) = (nx + ux, ny + uy);
+            }
+        } else if (a == Action::L || a == Action::R) && self.chance(noise.turn_error) {
+            let turn = if a == Action::L {-self.config.robot.turn()} else {self.config.robot.turn()};
+            nheading = if self.rng.gen::<bool>() {self.robot.heading} else {nheading.turn(turn)};
+        }
         let r =
             if a != Action::SUCK {
-                /* Check for collisions with obstacles and hazards */
-                let mut penalty = 0.0;
-                for (x, y) in Room::get_occupied_squares(nx, ny, ndirn) {
-                    if x >= 0 && x < self.xsize && y >= 0 && y < self.ysize {
-                        let v = self.board[(y * self.xsize + x) as usize];
-                        penalty = if v == -2 && penalty > -20.0 { -20.0 } else { penalty };
-                        penalty = if v == -3 && penalty > -50.0 { -50.0 } else { penalty };
-                    } else {
-                        penalty = if penalty > -20.0 { -20.0 } else { penalty };
-                    }
+                /* Check for collisions with obstacles, hazards, low
+                 * furniture and other robots. Moving diagonally, the robot
+                 * mustn't cut a corner: it has to fit at both squares it
+                 * passes between as well. */
+                let mut squares = self.shape_squares(&self.body, nx, ny, nheading);
+                for (vx, vy) in via {
+                    squares.extend(self.shape_squares(&self.body, vx, vy, nheading));
+                }
+                if (nx - self.robot.x) * (ny - self.robot.y) != 0 {
+                    squares.extend(self.shape_squares(&self.body, nx, self.robot.y, nheading));
+                    squares.extend(self.shape_squares(&self.body, self.robot.x, ny, nheading));
                 }
+                let mut penalty = self.collision(&squares);
                 if penalty < 0.0 {
-                    (nx, ny, ndirn) = (self.x, self.y, self.dirn);
+                    (nx, ny, nheading) = (self.robot.x, self.robot.y, self.robot.heading);
                 }
                 /* Apply movement penalty for forward (-0.1), reverse (-2.0) and rotation (-0.2) */
                 if a == Action::FORWARD {
@@ -251,122 +1677,303 @@ impl Room {
                 } else {
                     penalty -= 0.1;
                 }
+                /* Moving onto carpet takes longer */
+                if (nx, ny) != (self.robot.x, self.robot.y) {
+                    penalty -= self.drive_onto(nx, ny);
+                }
                 penalty
             } else {
-                if self.x == 1 && self.y == self.ysize - 3 {
-                    /* Robot is on charging pad, start a new level */
-                    self.generate_level();
-                    -0.2
-                } else {
-                    /* For every square covered by the vacuum, reduce dirt level by 1
-                     * Reward 1 for each dirt removed this way, minus a constant -0.1 */
-                    self.get_suction_range().iter().filter(|(x, y)| {
-                        if *x >= 0 && *x < self.xsize && *y >= 0 && *y < self.ysize
-                            && self.board[(y * self.xsize + x) as usize] > 0 {
-                            self.board[(y * self.xsize + x) as usize] -= 1;
-                            true
-                        } else {
-                            false
-                        }
-                    }).collect::<Vec<&(i32, i32)>>().len() as f32 * 1.0 - 0.1
-                }
+                /* Reward 1 for each dirt removed, minus a constant -0.1 */
+                let removed = self.suck(&self.suction_cells());
+                removed as f32 * 1.0 - 0.1
             }
         ;
 
         /* Apply movement */
-        (self.x, self.y, self.dirn) = (nx, ny, ndirn);
+        let before = self.footprint();
+        (self.robot.x, self.robot.y, self.robot.heading) = (nx, ny, nheading);
+        let r = if a != Action::SUCK {r + self.enter_hazards(&before)} else {r};
+        let r = r + self.trespass() + self.mission_progress();
 
-        self.r += r;
+        self.robot.r += r;
         r
     }
+    /* Starts the next level: a new layout (or the map again), with the robots
+     * back on the pad, fully charged, and the mission started over. Their
+     * rewards carry on. */
+    pub fn reset(&mut self) {
+        let rewards: Vec<f32> = self.robots().map(|b| b.r).collect();
+        self.place_robot();
+        self.generate_level();
+        self.robot.r = rewards[0];
+        for (b, r) in self.others.iter_mut().zip(&rewards[1..]) {
+            b.r = *r;
+        }
+        self.steps = 0;
+        self.complete = false;
+    }
     pub fn get_nn_input(&self) -> RoomVec {
-        /* Returns an input vector (len = SIZE_STATE = 403) for a neural network:
-         * - Values for a 20 x 20 space around the robot
+        self.get_nn_input_for(0)
+    }
+    pub fn get_nn_input_for(&self, robot: usize) -> RoomVec {
+        /* Returns an input vector (len = SIZE_STATE = 812) for a neural network:
+         * - Values for a 20 x 20 space around the robot, where movers and
+         *   other robots look like obstacles
          * - Coordinates (x, y) of the robot, relative to the charging pad
-         * - Direction in which the robot is facing */
+         * - Direction in which the robot is facing
+         * - The same 20 x 20 space, with 1 for no-go zones and 2 for virtual
+         *   walls
+         * - The mission: the middle of its area relative to the robot, half
+         *   the area's width and height, 1 if the robot should then dock,
+         *   the fraction of its steps left, and 1 once the area is clean
+         * - 1 if the robot is docked
+         * - How full its bin is, from 0 to 1 (always 0 if it never fills) */
+        let b = self.robots().nth(robot).expect("No such robot");
+        let others = self.blocked_squares(robot);
+        let mission = &self.config.mission;
+        let area = mission.bounds(&self.config.zones, self.xsize, self.ysize);
         std::array::from_fn(|i| {
             match i {
                 0..=399 => {
                     let (x, y): (i32, i32) = (i as i32 % 20, i as i32 / 20);
-                    let (x, y): (i32, i32) = (self.x + x - 9, self.y + y - 9);
-                    if x >= 0 && x < self.xsize && y >= 0 && y < self.ysize {
-                        self.board[(y * self.xsize + x) as usize] as f32
+                    let (x, y) = (b.x + x - 9, b.y + y - 9);
+                    if others.contains(&(x, y)) || self.too_low(x, y) {
+                        Cell::Obstacle.code() as f32
                     } else {
-                        /* Out of bounds, return an obstacle */
-                        -2.0
+                        /* Out of bounds squares are obstacles */
+                        self.cell(x, y).unwrap_or(Cell::Obstacle).code() as f32
+                    }
+                }
+                400..=402 if b.pose.is_some() => {
+                    /* Continuous robots give their exact pose */
+                    let (pose, (sx, sy)) = (b.pose.unwrap(), self.start_centre());
+                    [pose.x - sx, sy - pose.y, pose.theta / (std::f32::consts::PI / 2.0)][i - 400]
+                }
+                400 => (b.x - self.start().0) as f32,
+                401 => (self.start().1 - b.y) as f32,
+                402 => b.heading.value(),
+                403..=802 => {
+                    let (x, y): (i32, i32) = ((i as i32 - 403) % 20, (i as i32 - 403) / 20);
+                    match self.square(b.x + x - 9, b.y + y - 9).map(|sq| sq.zone) {
+                        Some(Zone::NoGo) => 1.0,
+                        Some(Zone::Wall) => 2.0,
+                        _ => 0.0,
                     }
                 }
-                400 => (self.x - 1) as f32,
-                401 => (self.ysize - self.y - 3) as f32,
-                402 => self.dirn as f32,
+                803 => (area.x0 + area.x1) as f32 / 2.0 - b.x as f32,
+                804 => b.y as f32 - (area.y0 + area.y1) as f32 / 2.0,
+                805 => (area.x1 - area.x0) as f32 / 2.0,
+                806 => (area.y1 - area.y0) as f32 / 2.0,
+                807 if mission.return_to_dock => 1.0,
+                807 => 0.0,
+                808 if mission.step_limit > 0 => 1.0 - self.steps as f32 / mission.step_limit as f32,
+                808 => 1.0,
+                809 if self.area_clean => 1.0,
+                809 => 0.0,
+                810 if b.docked => 1.0,
+                810 => 0.0,
+                811 if self.config.robot.bin_capacity > 0 => b.bin as f32 / self.config.robot.bin_capacity as f32,
+                811 => 0.0,
                 _   => 0.0, /* Should not occur */
             }
         })
     }
+    /* Total reward of all the robots: they clean the room together */
     pub fn get_total_reward(&self) -> f32 {
-        self.r
+        self.robots().map(|b| b.r).sum()
     }
-    pub fn draw(&self, first_time: bool) -> Result<()> {
-        /* If we're on the charging pad, it could be a new level */
-        let redraw_map = self.x == 1 && self.y == self.ysize - 3;
-
-        let mut stdout = stdout();
-        let draw_xmin;
-        let draw_xmax;
-        let draw_ymin;
-        let draw_ymax;
-        if first_time {
-            queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
+    pub fn robot_count(&self) -> usize {
+        1 + self.others.len()
+    }
+    pub fn get_config(&self) -> &LevelConfig {
+        &self.config
+    }
+    /* Changes how dirt builds up from now on. Rooms loaded from a map keep
+     * their layout; generated rooms pick up the new densities at the next
+     * level. The robot is moved back to the pad. */
+    pub fn set_config(&mut self, config: LevelConfig) {
+        self.config = config;
+        self.place_zones();
+        self.place_robot();
+        self.place_others();
+        self.place_movers();
+        self.compute_dirt_rates();
+    }
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+    pub fn is_from_map(&self) -> bool {
+        self.map.is_some()
+    }
+    /* Read-only view of the board, for tools that inspect rooms. Squares
+     * are addressed by (x, y) from the top-left corner. */
+    pub fn dimensions(&self) -> (i32, i32) {
+        (self.xsize, self.ysize)
+    }
+    /* What is on a square, or None if it's outside the room */
+    pub fn cell(&self, x: i32, y: i32) -> Option<Cell> {
+        self.square(x, y).map(|sq| sq.cell)
+    }
+    /* Floor covering of a square, or None if it's outside the room */
+    pub fn floor(&self, x: i32, y: i32) -> Option<Floor> {
+        self.square(x, y).map(|sq| sq.floor)
+    }
+    /* Every square of the room as (x, y, cell), row by row */
+    pub fn cells(&self) -> impl Iterator<Item = (i32, i32, Cell)> + '_ {
+        self.board.iter().enumerate()
+            .map(|(i, sq)| (i as i32 % self.xsize, i as i32 / self.xsize, sq.cell))
+    }
+    /* Whether the robots are kept off a square by a virtual wall, or a no-go
+     * zone if those block */
+    fn fenced(&self, x: i32, y: i32) -> bool {
+        self.square(x, y).is_some_and(|sq| sq.zone == Zone::Wall || (sq.zone == Zone::NoGo && self.config.block_no_go))
+    }
+    /* Penalty for the robot being moved ending an action in a no-go zone */
+    fn trespass(&self) -> f32 {
+        let inside = self.footprint().iter().any(|(x, y)| self.square(*x, *y).is_some_and(|sq| sq.zone == Zone::NoGo));
+        if inside {NO_GO_PENALTY} else {0.0}
+    }
+    /* Whether there's furniture over a square that the robots don't fit under */
+    fn too_low(&self, x: i32, y: i32) -> bool {
+        self.square(x, y).is_some_and(|sq| sq.clearance > 0 && sq.clearance < self.config.robot.height)
+    }
+    fn square(&self, x: i32, y: i32) -> Option<&Square> {
+        if x >= 0 && x < self.xsize && y >= 0 && y < self.ysize {
+            Some(&self.board[(y * self.xsize + x) as usize])
+        } else {
+            None
+        }
+    }
+    /* Returns the current board in the map file format read by from_map() */
+    pub fn get_map(&self) -> String {
+        let mut map = String::with_capacity(((self.xsize + 1) * self.ysize) as usize);
+        for row in self.board.chunks(self.xsize as usize) {
+            for sq in row {
+                map.push(match (sq.cell, sq.floor) {
+                    (Cell::Hazard, _) => '!',
+                    (Cell::Cliff, _) => 'V',
+                    (Cell::Cord, _) => '=',
+                    (Cell::Liquid, _) => '~',
+                    (Cell::Obstacle, _) => 'X',
+                    (Cell::Pad, _) => 'O',
+                    (Cell::Floor(0), _) if sq.zone == Zone::NoGo => 'N',
+                    (Cell::Floor(0), _) if sq.zone == Zone::Wall => '|',
+                    (Cell::Floor(0), _) if sq.clearance >= floorplan::TABLE => 'T',
+                    (Cell::Floor(0), _) if sq.clearance >= floorplan::BED => 'B',
+                    (Cell::Floor(0), _) if sq.clearance > 0 => 'S',
+                    (Cell::Floor(0), Floor::Carpet) => 'c',
+                    (Cell::Floor(0), Floor::Rug) => 'r',
+                    (Cell::Floor(0), _) => '.',
+                    (Cell::Floor(d), _) => char::from_digit(d.min(9) as u32, 10).unwrap(),
+                });
+            }
+            map.push('\n');
         }
-        if first_time || redraw_map {
-            draw_xmin = 0;
-            draw_xmax = self.xsize;
-            draw_ymin = 0;
-            draw_ymax = self.ysize;
+        map
+    }
+    /* Robot pose: the position of its origin square (see Room) and heading */
+    pub fn pose(&self) -> (i32, i32, Heading) {
+        (self.robot.x, self.robot.y, self.heading())
+    }
+    pub fn heading(&self) -> Heading {
+        self.robot.heading
+    }
+    /* Squares covered by the robot's body */
+    pub fn footprint(&self) -> Vec<(i32, i32)> {
+        self.robot_squares(&self.robot)
+    }
+    /* Squares under the suction head, from left to right as the robot sees them */
+    pub fn suction_cells(&self) -> Vec<(i32, i32)> {
+        self.robot_suction(&self.robot)
+    }
+    pub fn dirt_remaining(&self) -> i32 {
+        self.cells().map(|(_, _, cell)| cell.dirt()).sum()
+    }
+    /* Fraction of all dirt placed so far that has been sucked up */
+    pub fn get_dirt_coverage(&self) -> f32 {
+        if self.dirt_total > 0 {
+            self.dirt_removed as f32 / self.dirt_total as f32
         } else {
-            /* Just redraw an area around the robot. */
-            draw_xmin = if self.x - 5 >= 0 {self.x - 5} else {0};
-            draw_xmax = if self.x + 5 < self.xsize {self.x + 5} else {self.xsize};
-            draw_ymin = if self.y - 5 >= 0 {self.y - 5} else {0};
-            draw_ymax = if self.y + 5 < self.ysize {self.y + 5} else {self.ysize};
-        }
-
-        let suction_range = self.get_suction_range();
-
-        /* Draw room features */
-        for x in draw_xmin..draw_xmax {
-            for y in draw_ymin..draw_ymax {
-                let i: usize = (y * self.xsize + x) as usize;
-                let (xscr, yscr) = ((2 * x + 1) as u16, (y + 1) as u16);
-                queue!(stdout, cursor::MoveTo(xscr, yscr))?;
-                if suction_range.contains(&(x, y)) {
-                    queue!(stdout, SetBackgroundColor(Color::AnsiValue(236)))?;
-                }
-                match &self.board[i] {
-                    -3 => { queue!(stdout, SetForegroundColor(Color::Red), Print("!!"))?; }
-                    -2 => { queue!(stdout, SetForegroundColor(Color::Yellow), Print("XX"))?; }
-                    -1 => { queue!(stdout, SetForegroundColor(Color::Cyan), Print("OO"))?; }
-                    0 => { queue!(stdout, Print("  "))?; }
-                    d => {
-                        match d {
-                            1 => { queue!(stdout, SetForegroundColor(Color::AnsiValue(243)))? },
-                            2 => { queue!(stdout, SetForegroundColor(Color::AnsiValue(247)))? },
-                            3 => { queue!(stdout, SetForegroundColor(Color::AnsiValue(251)))? },
-                            _ => { queue!(stdout, SetForegroundColor(Color::AnsiValue(255)))? },
-                        }
-                        queue!(stdout, Print("<>"))?;
-                    }
+            1.0
+        }
+    }
+    /* Fraction of the floor on the current level that has been swept by the
+     * suction head. Obstacles and hazards don't count as floor. */
+    pub fn get_area_coverage(&self) -> f32 {
+        let (mut floor, mut swept) = (0, 0);
+        for (sq, visited) in self.board.iter().zip(self.visited.iter()) {
+            if sq.cell.is_floor() {
+                floor += 1;
+                swept += *visited as i32;
+            }
+        }
+        if floor > 0 {
+            swept as f32 / floor as f32
+        } else {
+            1.0
+        }
+    }
+    fn draw_square(&self, stdout: &mut io::Stdout, x: i32, y: i32, suction_range: &[(i32, i32)]) -> Result<()> {
+        let i: usize = (y * self.xsize + x) as usize;
+        let (xscr, yscr) = ((2 * x + 1) as u16, (y + 1) as u16);
+        queue!(stdout, cursor::MoveTo(xscr, yscr))?;
+        if suction_range.contains(&(x, y)) {
+            queue!(stdout, SetBackgroundColor(Color::AnsiValue(236)))?;
+        } else if self.board[i].zone == Zone::Wall {
+            queue!(stdout, SetBackgroundColor(Color::AnsiValue(90)))?;
+        } else if self.board[i].zone == Zone::NoGo {
+            queue!(stdout, SetBackgroundColor(Color::AnsiValue(52)))?;
+        } else if self.too_low(x, y) {
+            /* Furniture too low to get under */
+            queue!(stdout, SetBackgroundColor(Color::AnsiValue(94)))?;
+        } else if self.board[i].clearance > 0 {
+            queue!(stdout, SetBackgroundColor(Color::AnsiValue(239)))?;
+        } else if self.board[i].floor == Floor::Carpet {
+            queue!(stdout, SetBackgroundColor(Color::AnsiValue(53)))?;
+        } else if self.board[i].floor == Floor::Rug {
+            queue!(stdout, SetBackgroundColor(Color::AnsiValue(58)))?;
+        }
+        match self.board[i].cell {
+            Cell::Hazard => { queue!(stdout, SetForegroundColor(Color::Red), Print("!!"))?; }
+            Cell::Cliff => { queue!(stdout, SetForegroundColor(Color::DarkRed), Print("vv"))?; }
+            Cell::Cord => { queue!(stdout, SetForegroundColor(Color::DarkYellow), Print("=="))?; }
+            Cell::Liquid => { queue!(stdout, SetForegroundColor(Color::Blue), Print("~~"))?; }
+            Cell::Obstacle => { queue!(stdout, SetForegroundColor(Color::Yellow), Print("XX"))?; }
+            Cell::Pad => { queue!(stdout, SetForegroundColor(Color::Cyan), Print("OO"))?; }
+            Cell::Floor(0) => { queue!(stdout, Print("  "))?; }
+            Cell::Floor(d) => {
+                match d {
+                    1 => { queue!(stdout, SetForegroundColor(Color::AnsiValue(243)))? },
+                    2 => { queue!(stdout, SetForegroundColor(Color::AnsiValue(247)))? },
+                    3 => { queue!(stdout, SetForegroundColor(Color::AnsiValue(251)))? },
+                    _ => { queue!(stdout, SetForegroundColor(Color::AnsiValue(255)))? },
                 }
-                queue!(stdout, ResetColor)?;
+                queue!(stdout, Print("<>"))?;
             }
         }
-
-        /* Draw the robot */
-        let (xorig, yorig): (u16, u16) = ((2 * self.x + 1) as u16, (self.y + 1) as u16);
-        match self.dirn {
-            0 => {
+        queue!(stdout, ResetColor)?;
+        Ok(())
+    }
+    fn draw_robot(&self, stdout: &mut io::Stdout, b: &Robot) -> Result<()> {
+        let (xorig, yorig): (u16, u16) = ((2 * b.x + 1) as u16, (b.y + 1) as u16);
+        let default_model = self.config.robot.size == 4 && self.config.robot.suction_width == 4;
+        match b.heading {
+            _ if !default_model => {
+                /* Other robot models are drawn square by square, with the
+                 * front row marked */
+                let (dx, dy) = b.heading.step();
+                let footprint = self.robot_squares(b);
+                let front = footprint.iter().map(|(x, y)| x * dx + y * dy).max().unwrap_or(0);
+                for (x, y) in footprint {
+                    let at_front = x * dx + y * dy == front;
+                    queue!(*stdout,
+                           cursor::MoveTo((2 * x + 1) as u16, (y + 1) as u16),
+                           Print(if at_front {"##"} else {"[]"}))?;
+                }
+            }
+            Heading::Up => {
                 /* UP */
-                queue!(stdout,
+                queue!(*stdout,
                        cursor::MoveTo(xorig, yorig - 1),
                        Print("####"),
                        cursor::MoveTo(xorig - 2, yorig),
@@ -376,9 +1983,9 @@ impl Room {
                        cursor::MoveTo(xorig - 1, yorig + 2),
                        Print("`----'"))?;
             }
-            1 => {
+            Heading::Right => {
                 /* RIGHT */
-                queue!(stdout,
+                queue!(*stdout,
                        cursor::MoveTo(xorig - 1, yorig - 1),
                        Print(".----"),
                        cursor::MoveTo(xorig - 2, yorig),
@@ -388,9 +1995,9 @@ impl Room {
                        cursor::MoveTo(xorig - 1, yorig + 2),
                        Print("`----"))?;
             }
-            2 => {
+            Heading::Down => {
                 /* DOWN */
-                queue!(stdout,
+                queue!(*stdout,
                        cursor::MoveTo(xorig - 1, yorig - 1),
                        Print(",----."),
                        cursor::MoveTo(xorig - 2, yorig),
@@ -400,9 +2007,9 @@ impl Room {
                        cursor::MoveTo(xorig, yorig + 2),
                        Print("####"))?;
             }
-            3 => {
+            Heading::Left => {
                 /* LEFT */
-                queue!(stdout,
+                queue!(*stdout,
                        cursor::MoveTo(xorig, yorig - 1),
                        Print("----,"),
                        cursor::MoveTo(xorig - 2, yorig),
@@ -412,9 +2019,102 @@ impl Room {
                        cursor::MoveTo(xorig, yorig + 2),
                        Print("----'"))?;
             }
-            _ => {}
+            Heading::UpRight => {
+                queue!(*stdout,
+                       cursor::MoveTo(xorig - 1, yorig - 1),
+                       Print(".--##"),
+                       cursor::MoveTo(xorig - 2, yorig),
+                       Print("|     ##"),
+                       cursor::MoveTo(xorig - 2, yorig + 1),
+                       Print("| ╛    |"),
+                       cursor::MoveTo(xorig - 1, yorig + 2),
+                       Print("`----'"))?;
+            }
+            Heading::DownRight => {
+                queue!(*stdout,
+                       cursor::MoveTo(xorig - 1, yorig - 1),
+                       Print(",----."),
+                       cursor::MoveTo(xorig - 2, yorig),
+                       Print("| ╕    |"),
+                       cursor::MoveTo(xorig - 2, yorig + 1),
+                       Print("|     ##"),
+                       cursor::MoveTo(xorig - 1, yorig + 2),
+                       Print("`--##"))?;
+            }
+            Heading::DownLeft => {
+                queue!(*stdout,
+                       cursor::MoveTo(xorig - 1, yorig - 1),
+                       Print(",----."),
+                       cursor::MoveTo(xorig - 2, yorig),
+                       Print("|    ╒ |"),
+                       cursor::MoveTo(xorig - 2, yorig + 1),
+                       Print("##     |"),
+                       cursor::MoveTo(xorig, yorig + 2),
+                       Print("##--'"))?;
+            }
+            Heading::UpLeft => {
+                queue!(*stdout,
+                       cursor::MoveTo(xorig, yorig - 1),
+                       Print("##--."),
+                       cursor::MoveTo(xorig - 2, yorig),
+                       Print("##     |"),
+                       cursor::MoveTo(xorig - 2, yorig + 1),
+                       Print("|    ╘ |"),
+                       cursor::MoveTo(xorig - 1, yorig + 2),
+                       Print("`----'"))?;
+            }
+        }
+        queue!(*stdout, ResetColor)?;
+        Ok(())
+    }
+    /* Draws the room, all of it the first time or after a reset(), and then
+     * only what may have changed */
+    pub fn draw(&mut self, first_time: bool) -> Result<()> {
+        let mut stdout = stdout();
+        if first_time {
+            queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
+        }
+        /* Areas to redraw, as (xmin, xmax, ymin, ymax) */
+        let regions: Vec<(i32, i32, i32, i32)> = if first_time {
+            vec![(0, self.xsize, 0, self.ysize)]
+        } else {
+            /* Just redraw an area around each robot. */
+            let m = self.config.robot.size + 1;
+            self.robots().map(|b| {
+                ((b.x - m).max(0), (b.x + m).min(self.xsize), (b.y - m).max(0), (b.y + m).min(self.ysize))
+            }).collect()
+        };
+
+        let suction_range: Vec<(i32, i32)> = self.robots().flat_map(|b| self.robot_suction(b)).collect();
+
+        /* Draw room features, and anything that changed elsewhere */
+        for (xmin, xmax, ymin, ymax) in &regions {
+            for x in *xmin..*xmax {
+                for y in *ymin..*ymax {
+                    self.draw_square(&mut stdout, x, y, &suction_range)?;
+                }
+            }
+        }
+        for i in std::mem::take(&mut self.changed) {
+            let (x, y) = (i as i32 % self.xsize, i as i32 / self.xsize);
+            self.draw_square(&mut stdout, x, y, &suction_range)?;
+        }
+
+        /* Draw the movers and robots */
+        for m in &self.movers {
+            let (color, glyph) = match m.motion {
+                Motion::Wander => (Color::Magenta, "@@"),
+                Motion::Patrol => (Color::Green, "&&"),
+                Motion::Follow => (Color::DarkMagenta, "%%"),
+            };
+            for (x, y) in m.squares() {
+                queue!(stdout, cursor::MoveTo((2 * x + 1) as u16, (y + 1) as u16),
+                       SetForegroundColor(color), Print(glyph), ResetColor)?;
+            }
+        }
+        for b in self.robots() {
+            self.draw_robot(&mut stdout, b)?;
         }
-        queue!(stdout, ResetColor)?;
 
         if first_time {
             /* Draw border around room */
@@ -432,7 +2132,7 @@ impl Room {
                 queue!(stdout,
                        cursor::MoveTo(0, i + 1),
                        Print("\u{2551}"),
-                       cursor::MoveTo((self.xsize * 2 + 1) as u16, (i + 1) as u16),
+                       cursor::MoveTo((self.xsize * 2 + 1) as u16, i + 1),
                        Print("\u{2551}"))?;
             }
             queue!(stdout,
@@ -453,13 +2153,50 @@ impl Room {
                     ResetColor, Print(": Right arrow"),
                     cursor::MoveTo(0, (self.ysize + 5) as u16),
                     SetForegroundColor(Color::Cyan), Print("Suck"), ResetColor,
-                    Print(": Spacebar (doing so over the charging pad advances to the next level)")
+                    Print(": Spacebar | "),
+                    SetForegroundColor(Color::Cyan), Print("Dock"), ResetColor,
+                    Print(": D (on the pad, facing down) | "),
+                    SetForegroundColor(Color::Cyan), Print("Undock"), ResetColor,
+                    Print(": U | "),
+                    SetForegroundColor(Color::Cyan), Print("Next level"), ResetColor,
+                    Print(": N")
                     )?;
             queue!(stdout, cursor::MoveTo(0, (self.ysize + 7) as u16), SetForegroundColor(Color::Magenta),
                     Print("Quit"), ResetColor, Print(": ESC or Q"))?;
         }
         /* Information */
-        queue!(stdout, cursor::MoveTo(7, (self.ysize + 2) as u16), Print(format!("{:7.1}", self.r)))?;
+        queue!(stdout, cursor::MoveTo(7, (self.ysize + 2) as u16), Print(format!("{:7.1}", self.get_total_reward())))?;
+        let mut status = Vec::new();
+        if self.config.battery_drain > 0.0 {
+            let battery: Vec<String> = self.robots().map(|b| format!("{:3.0}%", 100.0 * b.battery.max(0.0))).collect();
+            status.push(format!("Battery: {}", battery.join(" ")));
+        }
+        if self.config.robot.bin_capacity > 0 {
+            let capacity = self.config.robot.bin_capacity as f32;
+            let bin: Vec<String> = self.robots().map(|b| format!("{:3.0}%", 100.0 * b.bin as f32 / capacity)).collect();
+            status.push(format!("Bin: {}", bin.join(" ")));
+        }
+        /* After the score, clear of the replay status on the line below */
+        queue!(stdout, cursor::MoveTo(16, (self.ysize + 2) as u16), terminal::Clear(terminal::ClearType::UntilNewLine),
+               Print(status.join(" | ")))?;
+        let separator = if status.is_empty() {""} else {" | "};
+        if self.robots().any(|b| b.fallen) {
+            queue!(stdout, Print(separator), SetForegroundColor(Color::Red), Print("Fell down the stairs!"), ResetColor)?;
+        } else if self.robot.docked {
+            queue!(stdout, Print(separator), SetForegroundColor(Color::Cyan), Print("Docked"), ResetColor)?;
+        }
+        if self.config.mission.is_set() {
+            let limit = self.config.mission.step_limit;
+            let status = if self.complete {
+                "done".to_string()
+            } else if limit > 0 {
+                format!("{} steps left", limit.saturating_sub(self.steps))
+            } else {
+                format!("{} steps", self.steps)
+            };
+            queue!(stdout, cursor::MoveTo(0, (self.ysize + 6) as u16), terminal::Clear(terminal::ClearType::CurrentLine),
+                   Print(format!("Mission: {} ({})", self.config.mission.describe(), status)))?;
+        }
 
         stdout.flush()?;
         Ok(())
@@ -467,3 +2204,142 @@ impl Room {
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room(pairs: &[(&str, &str)]) -> Room {
+        let pairs: Vec<(String, String)> = pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Room::with_config(40, 25, 11, LevelConfig::from_options(&Options::from_pairs(&pairs)))
+    }
+
+    fn corners(rects: &[Rect]) -> Vec<(i32, i32, i32, i32)> {
+        rects.iter().map(|r| (r.x0, r.y0, r.x1, r.y1)).collect()
+    }
+
+    #[test]
+    fn rects_include_both_corners_in_any_order() {
+        let rects = parse_rects("3,4,5,6/ 9, 2,7,1/", "no-go");
+        assert_eq!(corners(&rects), vec![(3, 4, 6, 7), (7, 1, 10, 3)]);
+        assert!(parse_rects("", "no-go").is_empty());
+        assert_eq!(corners(&parse_rects(&format_rects(&rects), "no-go")), corners(&rects));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid rectangle '1,2,3' for --virtual-walls")]
+    fn rects_need_four_numbers() {
+        parse_rects("1,2,3", "virtual-walls");
+    }
+
+    /* Clears the board and puts the robot in the middle of it, facing up */
+    fn open_floor(room: &mut Room) {
+        room.board.iter_mut().for_each(|sq| *sq = EMPTY);
+        (room.robot.x, room.robot.y, room.robot.heading) = (room.xsize / 2, room.ysize / 2, Heading::Up);
+    }
+
+    #[test]
+    fn wet_trail_covers_only_clean_floor_and_does_not_rewet() {
+        let mut room = room(&[]);
+        open_floor(&mut room);
+        let before = room.footprint();
+        let bottom = before.iter().map(|(_, y)| *y).max().unwrap();
+        let behind: Vec<(i32, i32)> = before.iter().filter(|(_, y)| *y == bottom).copied().collect();
+        let (dx, dy) = behind[0];
+        room.board[(dy * room.xsize + dx) as usize].cell = Cell::Floor(3);
+
+        room.robot.wet = WET_MOVES;
+        room.perform_action(Action::FORWARD);
+        assert_eq!(room.robot.wet, WET_MOVES - 1);
+        assert_eq!(room.cell(dx, dy), Some(Cell::Floor(3)));
+        for (x, y) in &behind[1..] {
+            assert_eq!(room.cell(*x, *y), Some(Cell::Liquid));
+        }
+
+        /* Backing onto the trail once the wheels are dry */
+        room.robot.wet = 0;
+        room.perform_action(Action::REVERSE);
+        assert_eq!(room.robot.wet, 0);
+    }
+
+    #[test]
+    fn start_is_clear_for_every_robot_size() {
+        for size in 3..=8 {
+            let size = size.to_string();
+            for rooms in ["0", "4"] {
+                let mut room = room(&[("robot-size", &size), ("suction-width", &size), ("rooms", rooms),
+                                      ("obstacle-density", "0.05"), ("hazard-density", "0.02"),
+                                      ("cliff-density", "0.02"), ("cord-density", "0.02")]);
+                for _ in 0..20 {
+                    for (x, y) in room.footprint().into_iter().chain(room.suction_cells()) {
+                        let cell = room.board[(y * room.xsize + x) as usize].cell;
+                        assert!(matches!(cell, Cell::Floor(_) | Cell::Pad),
+                                "size {} rooms {}: {:?} at ({}, {})", size, rooms, cell, x, y);
+                    }
+                    room.reset();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn clutter_leaves_every_room_reachable() {
+        let mut room = room(&[("rooms", "4"), ("obstacle-density", "0"), ("hazard-density", "0.03"),
+                              ("cliff-density", "0.03"), ("cord-density", "0.05"), ("liquid-density", "0.02")]);
+        let start = ((room.ysize - room.config.robot.size) * room.xsize) as usize;
+        for _ in 0..20 {
+            /* Everything but the obstacles is clutter */
+            let cells: Vec<Cell> = room.board.iter().map(|sq| sq.cell).collect();
+            let plan: Vec<Cell> = cells.iter()
+                .map(|c| if matches!(c, Cell::Obstacle | Cell::Pad) {*c} else {Cell::Floor(0)})
+                .collect();
+            let fits = room.body_fits(&cells);
+            let (reachable, reached) = (room.search(&room.body_fits(&plan), &[start]), room.search(&fits, &[start]));
+            assert!((0..fits.len()).all(|p| !fits[p] || reachable[p].is_none() || reached[p].is_some()));
+            room.reset();
+        }
+    }
+
+    #[test]
+    fn carpet_drains_the_battery_faster() {
+        let mut drained = Vec::new();
+        for floor in [Floor::Hard, Floor::Rug, Floor::Carpet] {
+            let mut room = room(&[("battery-drain", "0.01")]);
+            open_floor(&mut room);
+            room.board.iter_mut().for_each(|sq| sq.floor = floor);
+            let battery = room.robot.battery;
+            room.perform_action(Action::FORWARD);
+            drained.push(((battery - room.robot.battery) * 1000.0).round() / 1000.0);
+        }
+        assert_eq!(drained, vec![0.01, 0.014, 0.02]);
+    }
+
+    #[test]
+    fn full_bin_leaves_dirt_and_passes() {
+        let mut room = room(&[("bin-capacity", "4")]);
+        open_floor(&mut room);
+        room.robot.bin = 4;
+        let (x, y) = room.suction_cells()[0];
+        let i = (y * room.xsize + x) as usize;
+        room.board[i] = Square{cell: Cell::Floor(2), floor: Floor::Carpet, passes: Floor::Carpet.passes() - 1, ..EMPTY};
+        room.perform_action(Action::SUCK);
+        assert_eq!((room.board[i].cell, room.board[i].passes), (Cell::Floor(2), Floor::Carpet.passes() - 1));
+
+        /* Once emptied, the next pass lifts the dirt */
+        room.robot.bin = 0;
+        room.perform_action(Action::SUCK);
+        assert_eq!((room.board[i].cell, room.board[i].passes, room.robot.bin), (Cell::Floor(1), 0, 1));
+    }
+
+    #[test]
+    fn actions_are_drive_commands() {
+        let (mut by_action, mut by_drive) = (room(&[("continuous", "")]), room(&[("continuous", "")]));
+        let wheelbase = by_drive.config.robot.wheelbase();
+        let quarter = std::f32::consts::PI / 2.0;
+        for (a, d) in [(Action::R, Drive::spin(quarter, wheelbase)), (Action::FORWARD, Drive::straight(1.0)),
+                       (Action::L, Drive::spin(-quarter, wheelbase)), (Action::REVERSE, Drive::straight(-1.0))] {
+            assert_eq!(by_action.step(&[a]), by_drive.step_drive(&[d]));
+            assert_eq!(by_action.pose(), by_drive.pose());
+        }
+    }
+}
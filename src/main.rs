@@ -1,6 +1,24 @@
 mod game;
+mod continuous;
+mod curriculum;
+mod dqn;
+mod drqn;
+mod dynamic;
+mod eval;
+mod explore;
+mod floorplan;
+mod memory;
+mod metrics;
+mod mission;
+mod noisy;
+mod options;
+mod policy;
+mod ppo;
+mod recurrent;
+mod replay;
 
 use std::env;
+use std::time::Duration;
 
 use std::io::{
     stdout,
@@ -13,52 +31,54 @@ use crossterm::{
 };
 
 use tch::{
-    nn, nn::Sequential, nn::Module, nn::OptimizerConfig,
-    Device, Tensor, Reduction
+    nn, Device,
 };
 
 use rand::Rng;
 
 use game::{
-    Room, RoomVec
+    LevelConfig, Room, RoomVec
 };
 
+use curriculum::Curriculum;
+use policy::Policy;
+
+use options::Options;
+use replay::Recording;
+
 const NUM_EPISODES: usize = 1024;
 const EPISODE_LEN: usize = 1024;
 const BATCH_SIZE: usize = 256;
+const PLAY_EPSILON: f32 = 0.05;
+const GAMMA: f32 = 0.95;
 
-/* Structure of our network */
-fn net(vs: &nn::Path) -> Sequential {
-    nn::seq()
-        .add(nn::linear(vs, game::SIZE_STATE as i64, 256, Default::default()))
-        .add_fn(|x| x.relu())
-        .add(nn::linear(vs, 256, 128, Default::default()))
-        .add_fn(|x| x.relu())
-        .add(nn::linear(vs, 128, 128, Default::default()))
-        .add_fn(|x| x.relu())
-        .add(nn::linear(vs, 128, 64, Default::default()))
-        .add_fn(|x| x.relu())
-        .add(nn::linear(vs, 64, game::SIZE_ACTION as i64, Default::default()))
-}
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mode = if args.len() >= 2 {args[1].as_str()} else {""};
+    let opts = Options::parse(if args.len() >= 2 {&args[2..]} else {&[]});
 
-struct SARS {
-    /* State, Action, Reward, Next state */
-    s:      RoomVec,
-    a:      usize,
-    r:      f32,
-    s_next: RoomVec, 
-}
+    if mode == "eval" {
+        eval::run(&opts);
+        return;
+    }
+    if mode == "replay" {
+        replay::run(&opts);
+        return;
+    }
 
-fn main() {
     let (termw, termh) = terminal::size().unwrap();
     let (w, h): (i32, i32) = (((termw - 2) / 2) as i32, (termh - 8) as i32);
-
-    let args: Vec<String> = env::args().collect();
     
     /* Neural network parameters */
     let dev = Device::cuda_if_available();
-    let vs = nn::VarStore::new(dev);
-    let net = net(&vs.root());
+    let (vs, mut policy) = match opts.get_str("load") {
+        Some(path) if mode == "demo" => Policy::load(path),
+        _ => {
+            let vs = nn::VarStore::new(dev);
+            let policy = Policy::new(&vs, &opts);
+            (vs, policy)
+        }
+    };
 
     /* Mode of control
      * 0: User input mode
@@ -66,70 +86,23 @@ fn main() {
      */
     let input_mode;
 
-    if args.len() >= 2 && String::from("demo") == args[1] {
+    if mode == "demo" && opts.has("load") {
+        /* Play a previously trained policy */
+        input_mode = 1;
+    } else if mode == "demo" {
         /* Neural network demo mode */
         println!("Initialisaing training...");
-        let mut opt = nn::Adam::default().build(&vs, 3e-4).expect("Failed to build optimiser");
-        let mut rng = rand::thread_rng();
         println!("CUDA available? {}", dev.is_cuda());
-
-        /* Replay memory */
-        let mut rmem = Vec::<SARS>::with_capacity(NUM_EPISODES * EPISODE_LEN);
-        for ep in 0..NUM_EPISODES {
-            let mut room = Room::new(w, h);
-            for _ in 0..EPISODE_LEN {
-                let s = room.get_nn_input();
-                /* Epsilon-greedy action selection */
-                let a = if rng.gen::<f32>() < 0.2 {
-                    rng.gen_range(0..game::SIZE_ACTION)
-                } else {
-                    get_nn_best_action(&net, &s)
-                };
-                print!("{} ", a);
-                let r = room.perform_action(game::i_to_act(a));
-                let s_next = room.get_nn_input();
-                rmem.push(SARS{s, a, r, s_next});
-                if rmem.len() >= BATCH_SIZE {
-                    /* Sample from memory and learn */
-                    let sample = rand::seq::index::sample(&mut rng, rmem.len(), BATCH_SIZE)
-                        .iter().map(|i| &rmem[i]).collect::<Vec<&SARS>>();
-                    let mut s = Vec::with_capacity(game::SIZE_STATE * BATCH_SIZE);
-                    let mut a = Vec::with_capacity(BATCH_SIZE);
-                    let mut r = Vec::with_capacity(BATCH_SIZE);
-                    let mut s_next = Vec::with_capacity(game::SIZE_STATE * BATCH_SIZE);
-                    for sars in sample {
-                        s.extend(sars.s);
-                        a.push(sars.a);
-                        r.push(sars.r);
-                        s_next.extend(sars.s_next);
-                    }
-                    let q_next = net.forward(&Tensor::of_slice(&s_next).view((BATCH_SIZE as i64, game::SIZE_STATE as i64)));
-                    let fwd = net.forward(&Tensor::of_slice(&s).view((BATCH_SIZE as i64, game::SIZE_STATE as i64)));
-                    let max_next = Vec::from(q_next).chunks(game::SIZE_ACTION).into_iter().map(|slice| {
-                        let mut max = std::f32::MIN;
-                        for val in slice.iter() {
-                            max = if *val > max {*val} else {max}
-                        }
-                        max
-                    }).collect::<Vec<f32>>();
-                    let model_r: Tensor = Tensor::of_slice(&r) + 0.95 * Tensor::of_slice(&max_next);
-                    /* Modified forward tensor with expected reward values */
-                    let mut y: Vec<f32> = Vec::from(&fwd);
-                    for (i, chunk) in y.chunks_mut(game::SIZE_ACTION).into_iter().enumerate() {
-                        chunk[a[i]] = Vec::from(&model_r)[i];
-                    }
-                    let y = Tensor::of_slice(&y).view((BATCH_SIZE as i64, game::SIZE_ACTION as i64));
-                    let loss = fwd.mse_loss(&y, Reduction::Mean); /* Scalar tensor */
-                    print!("({:7.2e}) ", Vec::<f32>::from(&loss)[0]);
-                    opt.backward_step(&loss);
-                }
-            }
-            println!("");
-            let temp = room.get_nn_input();
-            for chunk in temp.chunks(20).into_iter() {
-                println!("{:2.0?}", chunk);
-            }
-            println!("Episode {:3} (of {}) Reward: {:7.1}", ep + 1, NUM_EPISODES, room.get_total_reward());
+        let mut curriculum = Curriculum::from_options(&opts, w, h);
+        match (opts.get_str("trainer").unwrap_or("dqn"), &policy) {
+            ("dqn", Policy::Feedforward(net)) => dqn::train(&opts, &vs, net, &mut curriculum),
+            ("ppo", Policy::Feedforward(net)) => ppo::train(&opts, &vs, net, &mut curriculum),
+            ("drqn", Policy::Recurrent(net, _)) => drqn::train(&opts, &vs, net, &mut curriculum),
+            (t, _) => panic!("Unknown trainer '{}', expected dqn, ppo or drqn", t),
+        }
+        if let Some(path) = opts.get_str("save") {
+            vs.save(path).unwrap_or_else(|e| panic!("Failed to save policy {}: {}", path, e));
+            println!("Policy saved to {}", path);
         }
         input_mode = 1;
     } else {
@@ -138,52 +111,92 @@ fn main() {
 
     /* Gameplay loop */
 
-    if input_mode == 0 {
-        terminal::enable_raw_mode().expect("Failed to enable RAW mode.");
-    }
+    terminal::enable_raw_mode().expect("Failed to enable RAW mode.");
     _ = stdout().execute(cursor::Hide);
 
-    let mut room = Room::new(w, h);
+    let play_epsilon = opts.get("play-epsilon", PLAY_EPSILON);
+    policy.reset();
+    let mut room = Room::with_config(w, h, rand::thread_rng().gen(), LevelConfig::from_options(&opts));
     _ = room.draw(true);
 
+    /* Optionally record the episode for later replay */
+    let mut recording = opts.get_str("record").map(|_| Recording::start(&room, vec![
+        ("mode".to_string(), if input_mode == 0 {"user".to_string()} else {"demo".to_string()}),
+        ("policy".to_string(), opts.get_str("load").unwrap_or("trained").to_string()),
+    ]));
+
     loop {
         let a: isize =
             if input_mode == 0 {
                 get_action_user()
             } else {
-                get_action_nn(&net, &room.get_nn_input())
+                if user_quit() {-1} else {get_action_nn(&mut policy, 0, &room.get_nn_input(), play_epsilon)}
             };
         if a == -1 {
             break;
         }
-        room.perform_action(game::i_to_act(a as usize));
+        if a == -2 {
+            next_level(&mut room, &mut policy, &mut recording);
+            continue;
+        }
+        /* Any other robots in the room are driven by the policy, each with
+         * a memory of its own */
+        let mut actions = vec![a as usize];
+        for i in 1..room.robot_count() {
+            actions.push(get_action_nn(&mut policy, i, &room.get_nn_input_for(i), play_epsilon) as usize);
+        }
+        let r = room.step(&actions.iter().map(|a| game::i_to_act(*a)).collect::<Vec<_>>()).iter().sum();
+        if let Some(rec) = &mut recording {
+            rec.push(actions, r);
+        }
         _ = room.draw(false);
+        if room.is_done() {
+            /* Leave the end of the episode on screen for a moment */
+            std::thread::sleep(Duration::from_secs(1));
+            next_level(&mut room, &mut policy, &mut recording);
+        }
     }
+    if let (Some(rec), Some(path)) = (&recording, opts.get_str("record")) {
+        rec.save(path).unwrap_or_else(|e| panic!("Failed to save recording {}: {}", path, e));
+    }
+    _ = terminal::disable_raw_mode();
     _ = stdout().execute(terminal::Clear(terminal::ClearType::All));
     _ = stdout().execute(cursor::Show);
 }
 
-fn get_nn_best_action(net: &Sequential, s: &RoomVec) -> usize {
-    let mut max = std::f32::MIN;
-    let mut argmax = 0;
-    let out = Vec::from(net.forward(&Tensor::of_slice(s)));
-    for (i, v) in out.iter().enumerate() {
-        if *v > max {
-            max = *v;
-            argmax = i;
-        }
+/* On to the next level, recording the reset */
+fn next_level(room: &mut Room, policy: &mut Policy, recording: &mut Option<Recording>) {
+    room.reset();
+    policy.reset();
+    if let Some(rec) = recording {
+        rec.push_reset();
     }
-    argmax
+    _ = room.draw(true);
 }
 
-fn get_action_nn(net: &Sequential, s: &RoomVec) -> isize {
-    /* Epsilon-greedy action selection */
+fn get_action_nn(policy: &mut Policy, robot: usize, s: &RoomVec, epsilon: f32) -> isize {
+    /* Epsilon-greedy action selection. The policy sees every state, so a
+     * recurrent policy keeps its memory even when a random action is taken. */
     let mut rng = rand::thread_rng();
-    if rng.gen::<f32>() < 0.05 {
+    let best = policy.best_action(robot, s);
+    if rng.gen::<f32>() < epsilon {
         rng.gen_range(0..game::SIZE_ACTION) as isize
     } else {
-        get_nn_best_action(net, s) as isize
+        best as isize
+    }
+}
+
+/* Checks, without blocking, whether the user has pressed ESC or Q */
+fn user_quit() -> bool {
+    while event::poll(Duration::ZERO).unwrap_or(false) {
+        match event::read() {
+            Ok(Event::Key(event::KeyEvent{code: event::KeyCode::Esc, ..})) |
+            Ok(Event::Key(event::KeyEvent{code: event::KeyCode::Char('q'), ..}))
+                => return true,
+            _ => {}
+        }
     }
+    false
 }
 
 fn get_action_user() -> isize {
@@ -200,6 +213,12 @@ fn get_action_user() -> isize {
                 => return 3,
             Event::Key(event::KeyEvent{code: event::KeyCode::Char(' '), ..})
                 => return 4,
+            Event::Key(event::KeyEvent{code: event::KeyCode::Char('d'), ..})
+                => return 5,
+            Event::Key(event::KeyEvent{code: event::KeyCode::Char('u'), ..})
+                => return 6,
+            Event::Key(event::KeyEvent{code: event::KeyCode::Char('n'), ..})
+                => return -2,
             Event::Key(event::KeyEvent{code: event::KeyCode::Esc, ..}) |
             Event::Key(event::KeyEvent{code: event::KeyCode::Char('q'), ..})
                 => return -1, 
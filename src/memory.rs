@@ -0,0 +1,186 @@
+use std::collections::VecDeque;
+use std::ops::Index;
+
+use crate::game::RoomVec;
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct SARS {
+    /* State, Action, Reward, Next state
+     * For n-step transitions, r is the discounted sum of the n rewards and
+     * s_next the state n steps later. The target is then
+     *   r + discount * max Q(s_next)
+     * where discount is gamma^n, or 0 if the episode ended in between. */
+    pub s:        RoomVec,
+    pub a:        usize,
+    pub r:        f32,
+    pub s_next:   RoomVec,
+    pub discount: f32,
+}
+
+/* Turns a stream of single steps into n-step transitions for the replay
+ * memory. A transition is emitted once n further steps are known, or the
+ * episode ends; if it's cut off instead, the remaining steps are flushed
+ * with shorter returns. */
+pub struct NStep {
+    n: usize,
+    gamma: f32,
+    pending: VecDeque<(RoomVec, usize, f32)>,
+    s_last: Option<RoomVec>,
+}
+
+impl NStep {
+    pub fn new(n: usize, gamma: f32) -> NStep {
+        NStep{n: n.max(1), gamma, pending: VecDeque::with_capacity(n), s_last: None}
+    }
+    /* Adds a step, returning the transitions it completes. A step that ends
+     * the episode completes all of them, and none bootstraps from the
+     * terminal state. */
+    pub fn push(&mut self, s: RoomVec, a: usize, r: f32, s_next: RoomVec, done: bool) -> Vec<SARS> {
+        self.pending.push_back((s, a, r));
+        self.s_last = Some(s_next);
+        if done {
+            self.drain(true)
+        } else if self.pending.len() >= self.n {
+            self.pop(false).into_iter().collect()
+        } else {
+            Vec::new()
+        }
+    }
+    /* Ends an episode cut off by the step limit, returning the transitions
+     * still waiting for steps, bootstrapped from the last state */
+    pub fn flush(&mut self) -> Vec<SARS> {
+        self.drain(false)
+    }
+    fn drain(&mut self, terminal: bool) -> Vec<SARS> {
+        let mut out = Vec::with_capacity(self.pending.len());
+        while let Some(t) = self.pop(terminal) {
+            out.push(t);
+        }
+        self.s_last = None;
+        out
+    }
+    fn pop(&mut self, terminal: bool) -> Option<SARS> {
+        let s_next = self.s_last?;
+        let mut r = 0.0;
+        let mut discount = 1.0;
+        for (_, _, ri) in self.pending.iter() {
+            r += discount * ri;
+            discount *= self.gamma;
+        }
+        let (s, a, _) = self.pending.pop_front()?;
+        Some(SARS{s, a, r, s_next, discount: if terminal {0.0} else {discount}})
+    }
+}
+
+/* Replay memory of fixed capacity: once it's full, each new item replaces
+ * the oldest. Items are in no particular order. */
+pub struct Ring<T> {
+    items: Vec<T>,
+    capacity: usize,
+    next: usize,
+}
+
+impl<T> Ring<T> {
+    pub fn new(capacity: usize) -> Ring<T> {
+        let capacity = capacity.max(1);
+        Ring{items: Vec::with_capacity(capacity), capacity, next: 0}
+    }
+    pub fn push(&mut self, item: T) {
+        if self.items.len() < self.capacity {
+            self.items.push(item);
+        } else {
+            self.items[self.next] = item;
+        }
+        self.next = (self.next + 1) % self.capacity;
+    }
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.items.iter()
+    }
+}
+
+impl<T> Index<usize> for Ring<T> {
+    type Output = T;
+    fn index(&self, i: usize) -> &T {
+        &self.items[i]
+    }
+}
+
+impl<T> Extend<T> for Ring<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, items: I) {
+        items.into_iter().for_each(|item| self.push(item));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::SIZE_STATE;
+
+    fn state(i: usize) -> RoomVec {
+        [i as f32; SIZE_STATE]
+    }
+
+    #[test]
+    fn ring_keeps_the_latest_items() {
+        let mut ring = Ring::new(3);
+        assert!(ring.is_empty());
+        ring.extend(0..5);
+        let mut items: Vec<i32> = ring.iter().copied().collect();
+        items.sort();
+        assert_eq!((ring.len(), items), (3, vec![2, 3, 4]));
+    }
+
+    #[test]
+    fn one_step_transitions_come_out_at_once() {
+        let mut nstep = NStep::new(1, 0.9);
+        let t = nstep.push(state(0), 2, 1.0, state(1), false);
+        assert_eq!(t.iter().map(|t| (t.s[0], t.a, t.r, t.s_next[0], t.discount)).collect::<Vec<_>>(),
+                   vec![(0.0, 2, 1.0, 1.0, 0.9)]);
+        assert!(nstep.flush().is_empty());
+    }
+
+    #[test]
+    fn three_step_returns_are_discounted() {
+        let mut nstep = NStep::new(3, 0.5);
+        assert!(nstep.push(state(0), 0, 1.0, state(1), false).is_empty());
+        assert!(nstep.push(state(1), 1, 2.0, state(2), false).is_empty());
+        let t = nstep.push(state(2), 2, 4.0, state(3), false);
+        assert_eq!(t.iter().map(|t| (t.s[0], t.a, t.r, t.s_next[0], t.discount)).collect::<Vec<_>>(),
+                   vec![(0.0, 0, 1.0 + 0.5 * 2.0 + 0.25 * 4.0, 3.0, 0.125)]);
+
+        /* Cut off by the episode length: the rest still bootstrap */
+        let rest = nstep.flush();
+        assert_eq!(rest.len(), 2);
+        assert_eq!((rest[0].a, rest[0].r, rest[0].s_next[0], rest[0].discount), (1, 2.0 + 0.5 * 4.0, 3.0, 0.25));
+        assert_eq!((rest[1].a, rest[1].r, rest[1].s_next[0], rest[1].discount), (2, 4.0, 3.0, 0.5));
+    }
+
+    #[test]
+    fn one_step_terminal_transition_does_not_bootstrap() {
+        let mut nstep = NStep::new(1, 0.9);
+        nstep.push(state(0), 0, 1.0, state(1), false);
+        let t = nstep.push(state(1), 3, -100.0, state(2), true);
+        assert_eq!(t.iter().map(|t| (t.a, t.r, t.discount)).collect::<Vec<_>>(), vec![(3, -100.0, 0.0)]);
+        assert!(nstep.flush().is_empty());
+    }
+
+    #[test]
+    fn three_step_terminal_window_does_not_bootstrap() {
+        let mut nstep = NStep::new(3, 0.5);
+        nstep.push(state(0), 0, 1.0, state(1), false);
+        nstep.push(state(1), 1, 2.0, state(2), false);
+        nstep.push(state(2), 2, 4.0, state(3), false);
+        /* Every window that reaches the last step ends with it */
+        let t = nstep.push(state(3), 3, 8.0, state(4), true);
+        assert_eq!(t.iter().map(|t| (t.a, t.r, t.discount)).collect::<Vec<_>>(),
+                   vec![(1, 2.0 + 0.5 * 4.0 + 0.25 * 8.0, 0.0), (2, 4.0 + 0.5 * 8.0, 0.0), (3, 8.0, 0.0)]);
+        assert!(nstep.flush().is_empty());
+        assert!(nstep.push(state(5), 0, 1.0, state(6), false).is_empty());
+    }
+}
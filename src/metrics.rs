@@ -0,0 +1,130 @@
+use std::fs;
+use std::io::{
+    self, BufWriter, Write,
+};
+
+use crate::options::Options;
+
+/* Mean, standard deviation and median of a set of values */
+pub fn stats(values: &[f32]) -> (f32, f32, f32) {
+    if values.is_empty() {
+        return (0.0, 0.0, 0.0);
+    }
+    let n = values.len() as f32;
+    let mean = values.iter().sum::<f32>() / n;
+    let std = (values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / n).sqrt();
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let mid = sorted.len() / 2;
+    let median = if sorted.len().is_multiple_of(2) {(sorted[mid - 1] + sorted[mid]) / 2.0} else {sorted[mid]};
+    (mean, std, median)
+}
+
+/* Training metrics for one episode */
+#[derive(Clone, Default)]
+pub struct Metrics {
+    pub episode: usize,
+    pub reward: f32,
+    pub loss: f32,
+    pub mean_q: f32,
+    pub epsilon: f32,
+    pub steps_per_sec: f32,
+    pub buffer_size: usize,
+    pub dirt_coverage: f32,
+    pub area_coverage: f32,
+    pub stage: usize,
+}
+
+impl Metrics {
+    const FIELDS: [&'static str; 10] = ["episode", "reward", "loss", "mean_q", "epsilon",
+                                        "steps_per_sec", "buffer_size", "dirt_coverage", "area_coverage",
+                                        "stage"];
+
+    fn values(&self) -> [String; 10] {
+        [self.episode.to_string(), self.reward.to_string(), self.loss.to_string(),
+         self.mean_q.to_string(), self.epsilon.to_string(), self.steps_per_sec.to_string(),
+         self.buffer_size.to_string(), self.dirt_coverage.to_string(), self.area_coverage.to_string(),
+         self.stage.to_string()]
+    }
+
+    /* Averages a set of metrics, keeping the latest episode, buffer size and stage */
+    fn mean(all: &[Metrics]) -> Metrics {
+        let n = all.len() as f32;
+        let last = all.last().cloned().unwrap_or_default();
+        let avg = |f: fn(&Metrics) -> f32| all.iter().map(f).sum::<f32>() / n;
+        Metrics{
+            episode: last.episode,
+            reward: avg(|m| m.reward),
+            loss: avg(|m| m.loss),
+            mean_q: avg(|m| m.mean_q),
+            epsilon: last.epsilon,
+            steps_per_sec: avg(|m| m.steps_per_sec),
+            buffer_size: last.buffer_size,
+            dirt_coverage: avg(|m| m.dirt_coverage),
+            area_coverage: avg(|m| m.area_coverage),
+            stage: last.stage,
+        }
+    }
+}
+
+/* Collects per-episode metrics and, every `interval` episodes, writes their
+ * average to the log file (CSV, or JSON lines if the path ends in .json or
+ * .jsonl) and prints a one-line progress summary. */
+pub struct MetricsLog {
+    file: Option<BufWriter<fs::File>>,
+    json: bool,
+    interval: usize,
+    total_episodes: usize,
+    pending: Vec<Metrics>,
+}
+
+impl MetricsLog {
+    pub fn new(path: Option<&str>, interval: usize, total_episodes: usize) -> io::Result<MetricsLog> {
+        let json = path.is_some_and(|p| p.ends_with(".json") || p.ends_with(".jsonl"));
+        let mut file = match path {
+            Some(p) => Some(BufWriter::new(fs::File::create(p)?)),
+            None => None,
+        };
+        if let (Some(f), false) = (&mut file, json) {
+            writeln!(f, "{}", Metrics::FIELDS.join(","))?;
+        }
+        Ok(MetricsLog{file, json, interval: interval.max(1), total_episodes, pending: Vec::new()})
+    }
+    /* Log options: --log <file> and --log-interval <episodes> */
+    pub fn from_options(opts: &Options, total_episodes: usize) -> MetricsLog {
+        let path = opts.get_str("log");
+        MetricsLog::new(path, opts.get("log-interval", 1), total_episodes)
+            .unwrap_or_else(|e| panic!("Failed to create log {}: {}", path.unwrap_or(""), e))
+    }
+    pub fn record(&mut self, m: Metrics) {
+        self.pending.push(m);
+        if self.pending.len() >= self.interval {
+            let m = Metrics::mean(&self.pending);
+            self.pending.clear();
+            self.print(&m);
+            if let Err(e) = self.write(&m) {
+                eprintln!("Failed to write log: {}", e);
+            }
+        }
+    }
+    fn print(&self, m: &Metrics) {
+        println!("Episode {:4}/{} | reward {:8.1} | loss {:8.2e} | Q {:7.2} | eps {:4.2} | {:6.0} steps/s | buf {:7} | dirt {:5.1}% | area {:5.1}%",
+                 m.episode, self.total_episodes, m.reward, m.loss, m.mean_q, m.epsilon,
+                 m.steps_per_sec, m.buffer_size, 100.0 * m.dirt_coverage, 100.0 * m.area_coverage);
+    }
+    fn write(&mut self, m: &Metrics) -> io::Result<()> {
+        let f = match &mut self.file {
+            Some(f) => f,
+            None => return Ok(()),
+        };
+        if self.json {
+            let fields = Metrics::FIELDS.iter().zip(m.values().iter())
+                .map(|(k, v)| format!("\"{}\": {}", k, v))
+                .collect::<Vec<String>>();
+            writeln!(f, "{{{}}}", fields.join(", "))?;
+        } else {
+            writeln!(f, "{}", m.values().join(","))?;
+        }
+        f.flush()
+    }
+}
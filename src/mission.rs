@@ -0,0 +1,102 @@
+use crate::floorplan::Rect;
+use crate::options::Options;
+
+/* Spot cleaning radius when none is given */
+const SPOT_RADIUS: i32 = 5;
+
+/* The part of the room a mission is about */
+#[derive(Clone, Default, PartialEq)]
+pub enum Area {
+    #[default]
+    Whole,
+    /* Squares within radius of (x, y) */
+    Spot { x: i32, y: i32, radius: i32 },
+    /* One of the named zones of the level */
+    Zone(String),
+}
+
+/* What the robot is asked to do in an episode: clean an area, within a
+ * number of steps (0: no limit), and then go back to the charging pad. The
+ * default is to clean the whole room for as long as the episode runs, which
+ * never finishes. */
+#[derive(Clone, Default)]
+pub struct Mission {
+    pub area: Area,
+    pub step_limit: usize,
+    pub return_to_dock: bool,
+}
+
+impl Mission {
+    /* Mission options:
+     *   --spot <x,y[,radius]>      Clean around a point (radius default: 5)
+     *   --zone <name>              Clean a zone named with --zones
+     *   --step-limit <N>           Stop after N steps
+     *   --return-to-dock           Finish on the charging pad */
+    pub fn from_options(opts: &Options) -> Mission {
+        let area = match (opts.get_str("spot"), opts.get_str("zone")) {
+            (Some(_), Some(_)) => panic!("Give either --spot or --zone, not both"),
+            (Some(spot), None) => {
+                let v: Vec<i32> = spot.split(',').map(|n| n.trim().parse().ok()).collect::<Option<_>>()
+                    .filter(|v: &Vec<i32>| v.len() == 2 || v.len() == 3)
+                    .unwrap_or_else(|| panic!("Invalid value '{}' for --spot, expected x,y or x,y,radius", spot));
+                Area::Spot{x: v[0], y: v[1], radius: v.get(2).copied().unwrap_or(SPOT_RADIUS)}
+            }
+            (None, Some(zone)) => Area::Zone(zone.to_string()),
+            (None, None) => Area::Whole,
+        };
+        Mission{area, step_limit: opts.get("step-limit", 0), return_to_dock: opts.has("return-to-dock")}
+    }
+    /* The options that from_options() reads back into this mission */
+    pub fn to_options(&self) -> Vec<(String, String)> {
+        let mut opts = vec![("step-limit".to_string(), self.step_limit.to_string())];
+        match &self.area {
+            Area::Whole => {}
+            Area::Spot{x, y, radius} => opts.push(("spot".to_string(), format!("{},{},{}", x, y, radius))),
+            Area::Zone(name) => opts.push(("zone".to_string(), name.clone())),
+        }
+        if self.return_to_dock {
+            opts.push(("return-to-dock".to_string(), String::new()));
+        }
+        opts
+    }
+    /* Whether the mission ever finishes, other than by falling down a cliff */
+    pub fn is_set(&self) -> bool {
+        self.area != Area::Whole || self.step_limit > 0 || self.return_to_dock
+    }
+    /* The rectangle around the area, given the zones of the level */
+    pub fn bounds(&self, zones: &[(String, Rect)], xsize: i32, ysize: i32) -> Rect {
+        match &self.area {
+            Area::Whole => Rect{x0: 0, y0: 0, x1: xsize, y1: ysize},
+            Area::Spot{x, y, radius} => Rect{x0: x - radius, y0: y - radius, x1: x + radius + 1, y1: y + radius + 1},
+            Area::Zone(name) => zone(zones, name),
+        }
+    }
+    /* Whether a square is in the area */
+    pub fn contains(&self, zones: &[(String, Rect)], x: i32, y: i32) -> bool {
+        match &self.area {
+            Area::Whole => true,
+            Area::Spot{x: sx, y: sy, radius} => (x - sx) * (x - sx) + (y - sy) * (y - sy) <= radius * radius,
+            Area::Zone(name) => zone(zones, name).contains(x, y),
+        }
+    }
+    /* A short description, for the screen */
+    pub fn describe(&self) -> String {
+        let mut text = match &self.area {
+            Area::Whole => "Clean the room".to_string(),
+            Area::Spot{x, y, radius} => format!("Clean within {} of ({}, {})", radius, x, y),
+            Area::Zone(name) => format!("Clean the {}", name),
+        };
+        if self.step_limit > 0 {
+            text += &format!(" in {} steps", self.step_limit);
+        }
+        if self.return_to_dock {
+            text += ", then dock";
+        }
+        text
+    }
+}
+
+fn zone(zones: &[(String, Rect)], name: &str) -> Rect {
+    zones.iter().find(|(n, _)| n == name).map(|(_, r)| *r)
+        .unwrap_or_else(|| panic!("No zone named '{}'", name))
+}
@@ -0,0 +1,51 @@
+use tch::{
+    nn, nn::Init, nn::ModuleT,
+    Kind, Tensor,
+};
+
+/* Initial noise scale of a layer, relative to 1/sqrt(inputs) */
+const SIGMA_0: f64 = 0.5;
+
+/* A linear layer with learned, factorised Gaussian noise on its weights
+ * (Fortunato et al., "Noisy Networks for Exploration"). In training mode a
+ * fresh noise sample is drawn on every forward pass, so the network explores
+ * by itself; otherwise only the mean weights are used. */
+#[derive(Debug)]
+pub struct NoisyLinear {
+    w_mu: Tensor,
+    w_sigma: Tensor,
+    b_mu: Tensor,
+    b_sigma: Tensor,
+    in_dim: i64,
+    out_dim: i64,
+}
+
+pub fn noisy_linear(vs: &nn::Path, in_dim: i64, out_dim: i64) -> NoisyLinear {
+    let bound = 1.0 / (in_dim as f64).sqrt();
+    NoisyLinear{
+        w_mu: vs.uniform("w_mu", &[out_dim, in_dim], -bound, bound),
+        w_sigma: vs.var("w_sigma", &[out_dim, in_dim], Init::Const(SIGMA_0 * bound)),
+        b_mu: vs.uniform("b_mu", &[out_dim], -bound, bound),
+        b_sigma: vs.var("b_sigma", &[out_dim], Init::Const(SIGMA_0 * bound)),
+        in_dim,
+        out_dim,
+    }
+}
+
+impl ModuleT for NoisyLinear {
+    fn forward_t(&self, xs: &Tensor, train: bool) -> Tensor {
+        if train {
+            /* f(x) = sgn(x) * sqrt(|x|) applied to unit Gaussian noise */
+            let noise = |n: i64| {
+                let x = Tensor::randn(&[n], (Kind::Float, self.w_mu.device()));
+                x.sign() * x.abs().sqrt()
+            };
+            let (eps_in, eps_out) = (noise(self.in_dim), noise(self.out_dim));
+            let w = &self.w_mu + &self.w_sigma * eps_out.outer(&eps_in);
+            let b = &self.b_mu + &self.b_sigma * eps_out;
+            xs.linear(&w, Some(&b))
+        } else {
+            xs.linear(&self.w_mu, Some(&self.b_mu))
+        }
+    }
+}
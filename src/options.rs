@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/* Command line options, given after the mode as `--name value` pairs.
+ * An option that isn't followed by a value (e.g. `--verbose`) is a flag. */
+pub struct Options {
+    opts: HashMap<String, String>,
+}
+
+impl Options {
+    pub fn parse(args: &[String]) -> Options {
+        let mut opts = HashMap::new();
+        let mut i = 0;
+        while i < args.len() {
+            if let Some(name) = args[i].strip_prefix("--") {
+                let value = match args.get(i + 1) {
+                    Some(v) if !v.starts_with("--") => {
+                        i += 1;
+                        v.clone()
+                    }
+                    _ => String::new(),
+                };
+                opts.insert(name.to_string(), value);
+            } else {
+                panic!("Unexpected argument '{}', options are given as --name value", args[i]);
+            }
+            i += 1;
+        }
+        Options{opts}
+    }
+    /* Options from name/value pairs, e.g. as saved in a file */
+    pub fn from_pairs(pairs: &[(String, String)]) -> Options {
+        Options{opts: pairs.iter().cloned().collect()}
+    }
+    pub fn has(&self, name: &str) -> bool {
+        self.opts.contains_key(name)
+    }
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.opts.get(name).map(|v| v.as_str())
+    }
+    /* Returns the parsed value of an option, or the default if it wasn't given */
+    pub fn get<T: FromStr>(&self, name: &str, default: T) -> T {
+        match self.opts.get(name) {
+            Some(v) => v.parse().unwrap_or_else(|_| panic!("Invalid value '{}' for --{}", v, name)),
+            None => default,
+        }
+    }
+}
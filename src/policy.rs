@@ -0,0 +1,107 @@
+use tch::{
+    nn, nn::SequentialT, nn::ModuleT,
+    Device, Tensor,
+};
+
+use crate::game::{
+    self, RoomVec,
+};
+use crate::explore;
+use crate::noisy;
+use crate::options::Options;
+use crate::recurrent::{
+    self, Hidden, RecurrentNet,
+};
+
+/* Structure of our network. A noisy network replaces the linear layers
+ * with NoisyLinear layers, see noisy.rs. */
+pub fn net(vs: &nn::Path, noisy: bool) -> SequentialT {
+    let layers = [game::SIZE_STATE as i64, 256, 128, 128, 64, game::SIZE_ACTION as i64];
+    let mut net = nn::seq_t();
+    for (i, dims) in layers.windows(2).enumerate() {
+        if i > 0 {
+            net = net.add_fn(|x| x.relu());
+        }
+        net = if noisy {
+            net.add(noisy::noisy_linear(&(vs / i), dims[0], dims[1]))
+        } else {
+            net.add(nn::linear(vs / i, dims[0], dims[1], Default::default()))
+        };
+    }
+    net
+}
+
+/* Q-values of a state. In training mode, a noisy network samples its noise. */
+pub fn get_nn_q(net: &SequentialT, s: &RoomVec, train: bool) -> Vec<f32> {
+    Vec::from(net.forward_t(&Tensor::of_slice(s), train))
+}
+
+/* A network that picks actions, one state at a time. A recurrent policy
+ * carries a hidden state for each robot it drives from step to step until
+ * it is reset. */
+pub enum Policy {
+    Feedforward(SequentialT),
+    Recurrent(Box<RecurrentNet>, Vec<Hidden>),
+}
+
+impl Policy {
+    /* Builds an untrained policy for the trainer chosen by the options:
+     * --trainer drqn with --cell <lstm|gru> (default: lstm) is recurrent,
+     * anything else is feedforward (noisy with --explore noisy). */
+    pub fn new(vs: &nn::VarStore, opts: &Options) -> Policy {
+        if opts.get_str("trainer") == Some("drqn") {
+            let lstm = match opts.get_str("cell").unwrap_or("lstm") {
+                "lstm" => true,
+                "gru" => false,
+                c => panic!("Unknown recurrent cell '{}', expected lstm or gru", c),
+            };
+            Policy::Recurrent(Box::new(recurrent::recurrent_net(&vs.root(), lstm)), Vec::new())
+        } else {
+            Policy::Feedforward(net(&vs.root(), opts.get_str("explore") == Some("noisy")))
+        }
+    }
+    /* Loads a checkpoint saved by training, working out from the saved
+     * variables which kind of network it holds. */
+    pub fn load(path: &str) -> (nn::VarStore, Policy) {
+        let tensors = Tensor::load_multi(path)
+            .unwrap_or_else(|e| panic!("Failed to load policy {}: {}", path, e));
+        let mut vs = nn::VarStore::new(Device::cuda_if_available());
+        let rnn = tensors.iter().find(|(name, _)| name == "rnn.weight_hh_l0");
+        let policy = match rnn {
+            Some((_, w)) => {
+                /* LSTMs have four gates, GRUs three */
+                Policy::Recurrent(Box::new(recurrent::recurrent_net(&vs.root(), w.size()[0] == 4 * recurrent::HIDDEN)), Vec::new())
+            }
+            None => {
+                let noisy = tensors.iter().any(|(name, _)| name.ends_with("sigma"));
+                Policy::Feedforward(net(&vs.root(), noisy))
+            }
+        };
+        vs.load(path).unwrap_or_else(|e| panic!("Failed to load policy {}: {}", path, e));
+        (vs, policy)
+    }
+    /* Forgets the hidden states, at the start of an episode */
+    pub fn reset(&mut self) {
+        if let Policy::Recurrent(_, hs) = self {
+            hs.clear();
+        }
+    }
+    /* Q-values (or action scores) of the next state in the episode seen by
+     * one of the robots, numbered from 0 */
+    pub fn q(&mut self, robot: usize, s: &RoomVec) -> Vec<f32> {
+        match self {
+            Policy::Feedforward(net) => get_nn_q(net, s, false),
+            Policy::Recurrent(net, hs) => tch::no_grad(|| {
+                while hs.len() <= robot {
+                    hs.push(net.zero_state(1));
+                }
+                let (q, h_next) = net.seq(&Tensor::of_slice(s).view((1, 1, game::SIZE_STATE as i64)), &hs[robot]);
+                hs[robot] = h_next;
+                Vec::from(q)
+            }),
+        }
+    }
+    pub fn best_action(&mut self, robot: usize, s: &RoomVec) -> usize {
+        explore::argmax(&self.q(robot, s))
+    }
+}
@@ -0,0 +1,186 @@
+use std::time::Instant;
+
+use rand::seq::SliceRandom;
+
+use tch::{
+    nn, nn::Module, nn::ModuleT, nn::Sequential, nn::SequentialT, nn::OptimizerConfig,
+    Kind, Tensor, Reduction,
+};
+
+use crate::game;
+use crate::curriculum::Curriculum;
+use crate::explore;
+use crate::metrics::{
+    self, Metrics, MetricsLog,
+};
+use crate::options::Options;
+use crate::{
+    NUM_EPISODES, EPISODE_LEN, BATCH_SIZE, GAMMA,
+};
+
+const PPO_EPOCHS: usize = 4;
+const CLIP: f64 = 0.2;
+const GAE_LAMBDA: f32 = 0.95;
+const ENTROPY_COEF: f64 = 0.01;
+const VALUE_COEF: f64 = 0.5;
+
+/* State-value network. It lives under "critic" in the same VarStore as the
+ * policy, so a PPO checkpoint can still be loaded as a plain policy. */
+fn critic(vs: &nn::Path) -> Sequential {
+    nn::seq()
+        .add(nn::linear(vs / 0, game::SIZE_STATE as i64, 256, Default::default()))
+        .add_fn(|x| x.relu())
+        .add(nn::linear(vs / 1, 256, 64, Default::default()))
+        .add_fn(|x| x.relu())
+        .add(nn::linear(vs / 2, 64, 1, Default::default()))
+}
+
+/* Generalised advantage estimates and returns for one episode. The state
+ * after the last step is worth v_last: its estimated value if the episode was
+ * cut off by the step limit, 0 if it ended. */
+fn gae(rewards: &[f32], values: &[f32], v_last: f32, gamma: f32, lambda: f32) -> (Vec<f32>, Vec<f32>) {
+    let mut adv = vec![0.0; rewards.len()];
+    let mut next_adv = 0.0;
+    let mut next_v = v_last;
+    for t in (0..rewards.len()).rev() {
+        let delta = rewards[t] + gamma * next_v - values[t];
+        next_adv = delta + gamma * lambda * next_adv;
+        adv[t] = next_adv;
+        next_v = values[t];
+    }
+    let returns = adv.iter().zip(values.iter()).map(|(a, v)| a + v).collect();
+    (adv, returns)
+}
+
+/* Trains the policy network with PPO (clipped objective, GAE and an entropy
+ * bonus) on rooms from the curriculum. Each episode is one rollout.
+ * Options:
+ *   --ppo-epochs <N>    Passes over each rollout (default: 4)
+ *   --clip <eps>        Ratio clipping range (default: 0.2)
+ *   --gae-lambda <l>    GAE lambda (default: 0.95)
+ *   --entropy <c>       Entropy bonus coefficient (default: 0.01)
+ *   --gamma <g>         Discount (default: GAMMA)
+ * In the logged metrics, mean_q is the mean state value from the critic. */
+pub fn train(opts: &Options, vs: &nn::VarStore, actor: &SequentialT, curriculum: &mut Curriculum) {
+    let critic = critic(&(vs.root() / "critic"));
+    let mut opt = nn::Adam::default().build(vs, 3e-4).expect("Failed to build optimiser");
+    let mut rng = rand::thread_rng();
+    let mut log = MetricsLog::from_options(opts, NUM_EPISODES);
+
+    let epochs = opts.get("ppo-epochs", PPO_EPOCHS);
+    let clip = opts.get("clip", CLIP);
+    let lambda = opts.get("gae-lambda", GAE_LAMBDA);
+    let entropy_coef = opts.get("entropy", ENTROPY_COEF);
+    let gamma = opts.get("gamma", GAMMA);
+
+    for ep in 0..NUM_EPISODES {
+        let mut room = curriculum.room();
+        let start = Instant::now();
+
+        /* Collect a rollout with the current policy */
+        let mut states = Vec::with_capacity(game::SIZE_STATE * EPISODE_LEN);
+        let mut actions = Vec::with_capacity(EPISODE_LEN);
+        let mut logps = Vec::with_capacity(EPISODE_LEN);
+        let mut values = Vec::with_capacity(EPISODE_LEN);
+        let mut rewards = Vec::with_capacity(EPISODE_LEN);
+        for _ in 0..EPISODE_LEN {
+            let s = room.get_nn_input();
+            let (probs, v) = tch::no_grad(|| {
+                let st = Tensor::of_slice(&s);
+                (Vec::<f32>::from(actor.forward_t(&st, true).softmax(-1, Kind::Float)),
+                 Vec::<f32>::from(critic.forward(&st))[0])
+            });
+            let a = explore::sample(&probs, &mut rng);
+            let r = room.perform_action(game::i_to_act(a));
+            states.extend(s);
+            actions.push(a as i64);
+            logps.push(probs[a].max(1e-8).ln());
+            values.push(v);
+            rewards.push(r);
+            if room.is_done() {
+                break;
+            }
+        }
+        /* Nothing follows the end of an episode, only the step limit */
+        let v_last = if room.is_done() {
+            0.0
+        } else {
+            tch::no_grad(|| Vec::<f32>::from(critic.forward(&Tensor::of_slice(&room.get_nn_input())))[0])
+        };
+        let steps = rewards.len();
+        let (mut adv, returns) = gae(&rewards, &values, v_last, gamma, lambda);
+        let (adv_mean, adv_std, _) = metrics::stats(&adv);
+        adv.iter_mut().for_each(|a| *a = (*a - adv_mean) / (adv_std + 1e-8));
+
+        /* Optimise the clipped surrogate objective over minibatches */
+        let states = Tensor::of_slice(&states).view((steps as i64, game::SIZE_STATE as i64));
+        let actions = Tensor::of_slice(&actions);
+        let logps = Tensor::of_slice(&logps);
+        let adv = Tensor::of_slice(&adv);
+        let returns = Tensor::of_slice(&returns);
+        let mut order: Vec<i64> = (0..steps as i64).collect();
+        let (mut loss_sum, mut n_updates) = (0.0, 0);
+        for _ in 0..epochs {
+            order.shuffle(&mut rng);
+            for batch in order.chunks(BATCH_SIZE) {
+                let idx = Tensor::of_slice(batch);
+                let s = states.index_select(0, &idx);
+                let logp_all = actor.forward_t(&s, true).log_softmax(-1, Kind::Float);
+                let logp = logp_all.gather(1, &actions.index_select(0, &idx).unsqueeze(1), false).squeeze_dim(1);
+                let ratio = (logp - logps.index_select(0, &idx)).exp();
+                let adv_b = adv.index_select(0, &idx);
+                let surr1 = &ratio * &adv_b;
+                let surr2 = ratio.clamp(1.0 - clip, 1.0 + clip) * &adv_b;
+                let policy_loss = -surr1.min_other(&surr2).mean(Kind::Float);
+                let value_loss = critic.forward(&s).squeeze_dim(1)
+                    .mse_loss(&returns.index_select(0, &idx), Reduction::Mean);
+                let entropy = -(logp_all.exp() * &logp_all)
+                    .sum_dim_intlist(Some(&[1i64][..]), false, Kind::Float)
+                    .mean(Kind::Float);
+                let loss = policy_loss + VALUE_COEF * value_loss - entropy_coef * entropy;
+                loss_sum += Vec::<f32>::from(&loss)[0];
+                n_updates += 1;
+                opt.backward_step(&loss);
+            }
+        }
+
+        log.record(Metrics{
+            episode: ep + 1,
+            reward: room.get_total_reward(),
+            loss: if n_updates > 0 {loss_sum / n_updates as f32} else {0.0},
+            mean_q: metrics::stats(&values).0,
+            epsilon: 0.0,
+            steps_per_sec: steps as f32 / start.elapsed().as_secs_f32(),
+            buffer_size: steps,
+            dirt_coverage: room.get_dirt_coverage(),
+            area_coverage: room.get_area_coverage(),
+            stage: curriculum.stage(),
+        });
+        curriculum.record(&room);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: &[f32], b: &[f32]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-5)
+    }
+
+    #[test]
+    fn lambda_one_gives_discounted_returns() {
+        /* With lambda = 1 the returns are the plain discounted sums,
+         * bootstrapped from v_last, whatever the values */
+        let (adv, ret) = gae(&[1.0, 2.0, 3.0], &[0.5, -1.0, 2.0], 10.0, 0.5, 1.0);
+        assert!(close(&ret, &[1.0 + 0.5 * 2.0 + 0.25 * 3.0 + 0.125 * 10.0, 2.0 + 0.5 * 3.0 + 0.25 * 10.0, 3.0 + 0.5 * 10.0]));
+        assert!(close(&adv, &[3.5, 7.0, 6.0]));
+    }
+
+    #[test]
+    fn lambda_zero_gives_one_step_errors() {
+        let (adv, ret) = gae(&[1.0, 2.0], &[0.5, 1.0], 4.0, 0.5, 0.0);
+        assert!(close(&adv, &[1.0 + 0.5 * 1.0 - 0.5, 2.0 + 0.5 * 4.0 - 1.0]));
+        assert!(close(&ret, &[1.5, 4.0]));
+    }
+}
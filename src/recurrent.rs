@@ -0,0 +1,71 @@
+use tch::{
+    nn, nn::Module, nn::RNN,
+    Tensor,
+};
+
+use crate::game;
+
+/* Size of the recurrent layer's hidden state */
+pub const HIDDEN: i64 = 128;
+
+/* Recurrent Q-network (DRQN): each observation is encoded by a linear layer,
+ * fed through an LSTM or GRU, and the recurrent output is mapped to
+ * Q-values. The hidden state lets the agent remember where it has been. */
+#[derive(Debug)]
+pub struct RecurrentNet {
+    encoder: nn::Linear,
+    cell: Cell,
+    head: nn::Linear,
+}
+
+#[derive(Debug)]
+enum Cell {
+    Lstm(nn::LSTM),
+    Gru(nn::GRU),
+}
+
+/* Hidden state of a RecurrentNet, for a batch of sequences */
+#[derive(Debug)]
+pub enum Hidden {
+    Lstm(nn::LSTMState),
+    Gru(nn::GRUState),
+}
+
+pub fn recurrent_net(vs: &nn::Path, lstm: bool) -> RecurrentNet {
+    RecurrentNet{
+        encoder: nn::linear(vs / "encoder", game::SIZE_STATE as i64, 256, Default::default()),
+        cell: if lstm {
+            Cell::Lstm(nn::lstm(vs / "rnn", 256, HIDDEN, Default::default()))
+        } else {
+            Cell::Gru(nn::gru(vs / "rnn", 256, HIDDEN, Default::default()))
+        },
+        head: nn::linear(vs / "head", HIDDEN, game::SIZE_ACTION as i64, Default::default()),
+    }
+}
+
+impl RecurrentNet {
+    pub fn zero_state(&self, batch: i64) -> Hidden {
+        match &self.cell {
+            Cell::Lstm(c) => Hidden::Lstm(c.zero_state(batch)),
+            Cell::Gru(c) => Hidden::Gru(c.zero_state(batch)),
+        }
+    }
+    /* Runs a batch of state sequences, [batch, time, SIZE_STATE], from the
+     * given hidden state. Returns Q-values, [batch, time, SIZE_ACTION], and
+     * the hidden state after the last step. */
+    pub fn seq(&self, states: &Tensor, h: &Hidden) -> (Tensor, Hidden) {
+        let x = self.encoder.forward(states).relu();
+        let (out, h) = match (&self.cell, h) {
+            (Cell::Lstm(c), Hidden::Lstm(h)) => {
+                let (out, h) = c.seq_init(&x, h);
+                (out, Hidden::Lstm(h))
+            }
+            (Cell::Gru(c), Hidden::Gru(h)) => {
+                let (out, h) = c.seq_init(&x, h);
+                (out, Hidden::Gru(h))
+            }
+            _ => panic!("Hidden state doesn't match the recurrent cell"),
+        };
+        (self.head.forward(&out), h)
+    }
+}
@@ -0,0 +1,297 @@
+use std::fs;
+use std::io::{
+    self, stdout, BufWriter, Write,
+};
+use std::time::Duration;
+
+use crossterm::{
+    event, event::Event,
+    terminal, cursor,
+    style::Print,
+    ExecutableCommand, QueueableCommand,
+};
+
+use crate::game::{
+    self, LevelConfig, Room,
+};
+use crate::options::Options;
+
+/* A recorded episode: enough to re-simulate it exactly.
+ *
+ * File format (text):
+ *   robovac-replay 1
+ *   size <xsize> <ysize>
+ *   source seed <seed>    or    source map
+ *   config <key> <value>  (any number of these)
+ *   level <key> <value>   (level generation options, any number of these)
+ *   board
+ *   <ysize rows of the initial board, in map file format>
+ *   steps
+ *   <actions> <reward>    (one line per step: the action of each robot,
+ *                          separated by commas, and their total reward)
+ *   reset                 (in place of a step: on to the next level)
+ * A reset is kept as a step with no actions. */
+pub struct Recording {
+    xsize: i32,
+    ysize: i32,
+    seed: Option<u64>, /* None if the room was loaded from a map */
+    config: Vec<(String, String)>,
+    level: Vec<(String, String)>,
+    board: String,
+    steps: Vec<(Vec<usize>, f32)>,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl Recording {
+    /* Starts a recording of a room that hasn't been played yet */
+    pub fn start(room: &Room, config: Vec<(String, String)>) -> Recording {
+        let (xsize, ysize) = room.dimensions();
+        Recording{
+            xsize, ysize,
+            seed: if room.is_from_map() {None} else {Some(room.get_seed())},
+            config,
+            level: room.get_config().to_options(),
+            board: room.get_map(),
+            steps: Vec::new(),
+        }
+    }
+    pub fn push(&mut self, actions: Vec<usize>, r: f32) {
+        self.steps.push((actions, r));
+    }
+    pub fn push_reset(&mut self) {
+        self.steps.push((Vec::new(), 0.0));
+    }
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut f = BufWriter::new(fs::File::create(path)?);
+        writeln!(f, "robovac-replay 1")?;
+        writeln!(f, "size {} {}", self.xsize, self.ysize)?;
+        match self.seed {
+            Some(seed) => writeln!(f, "source seed {}", seed)?,
+            None => writeln!(f, "source map")?,
+        }
+        for (k, v) in &self.config {
+            writeln!(f, "config {} {}", k, v)?;
+        }
+        for (k, v) in &self.level {
+            writeln!(f, "level {} {}", k, v)?;
+        }
+        write!(f, "board\n{}", self.board)?;
+        writeln!(f, "steps")?;
+        for (actions, r) in &self.steps {
+            if actions.is_empty() {
+                writeln!(f, "reset")?;
+                continue;
+            }
+            let actions: Vec<String> = actions.iter().map(|a| a.to_string()).collect();
+            writeln!(f, "{} {}", actions.join(","), r)?;
+        }
+        f.flush()
+    }
+    pub fn load(path: &str) -> io::Result<Recording> {
+        let text = fs::read_to_string(path)?;
+        let mut lines = text.lines();
+        if lines.next() != Some("robovac-replay 1") {
+            return Err(invalid(format!("{}: not a replay file", path)));
+        }
+        let mut rec = Recording{xsize: 0, ysize: 0, seed: None, config: Vec::new(),
+                                level: Vec::new(), board: String::new(), steps: Vec::new()};
+        while let Some(line) = lines.next() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["size", x, y] => {
+                    rec.xsize = x.parse().map_err(|_| invalid(format!("{}: bad size", path)))?;
+                    rec.ysize = y.parse().map_err(|_| invalid(format!("{}: bad size", path)))?;
+                }
+                ["source", "seed", seed] => {
+                    rec.seed = Some(seed.parse().map_err(|_| invalid(format!("{}: bad seed", path)))?);
+                }
+                ["source", "map"] => rec.seed = None,
+                ["config", k, v @ ..] => rec.config.push((k.to_string(), v.join(" "))),
+                ["level", k, v @ ..] => rec.level.push((k.to_string(), v.join(" "))),
+                ["board"] => {
+                    for _ in 0..rec.ysize {
+                        let row = lines.next().ok_or_else(|| invalid(format!("{}: board is truncated", path)))?;
+                        rec.board.push_str(row);
+                        rec.board.push('\n');
+                    }
+                }
+                ["steps"] => break,
+                _ => return Err(invalid(format!("{}: unexpected line '{}'", path, line))),
+            }
+        }
+        for line in lines {
+            let step = match line.split_once(' ') {
+                None if line == "reset" => Some((Vec::new(), 0.0)),
+                Some((a, r)) => a.split(',').map(|a| a.parse().ok()).collect::<Option<Vec<usize>>>().zip(r.parse().ok()),
+                None => None,
+            };
+            rec.steps.push(step.ok_or_else(|| invalid(format!("{}: bad step '{}'", path, line)))?);
+        }
+        Ok(rec)
+    }
+    /* Rebuilds the room as it was when the recording started */
+    pub fn room(&self) -> io::Result<Room> {
+        let config = LevelConfig::from_options(&Options::from_pairs(&self.level));
+        let room = match self.seed {
+            Some(seed) => Room::with_config(self.xsize, self.ysize, seed, config),
+            None => {
+                let mut room = Room::parse_map(&self.board, "replay")?;
+                room.set_config(config);
+                room
+            }
+        };
+        if room.get_map() != self.board {
+            return Err(invalid("board generated from seed differs from the recording".to_string()));
+        }
+        Ok(room)
+    }
+}
+
+fn draw_status(room: &Room, step: usize, total: usize, speed: f32, paused: bool, desync: bool) -> io::Result<()> {
+    let (_, ysize) = room.dimensions();
+    let mut stdout = stdout();
+    stdout.queue(cursor::MoveTo(0, (ysize + 3) as u16))?;
+    stdout.queue(terminal::Clear(terminal::ClearType::CurrentLine))?;
+    let (x, y, heading) = room.pose();
+    stdout.queue(Print(format!("Replay: step {}/{} | {:5.1} steps/s | robot ({}, {}) {:?} | {}{}",
+                               step, total, speed, x, y, heading,
+                               if paused {"PAUSED (n: step)"} else {"playing"},
+                               if desync {" | DESYNC: rewards differ from recording"} else {""})))?;
+    stdout.flush()
+}
+
+/* Entry point for `replay` mode:
+ *   --file <file>     Recording to play back (required)
+ *   --speed <steps/s> Playback speed (default: 10)
+ * Controls: space pauses, n steps while paused, +/- change speed, q quits. */
+pub fn run(opts: &Options) {
+    let path = opts.get_str("file").expect("replay requires --file <recording>");
+    let rec = Recording::load(path).unwrap_or_else(|e| panic!("Failed to load replay: {}", e));
+    if rec.is_empty() {
+        println!("{}: nothing to replay, the recording has no steps", path);
+        return;
+    }
+    let mut room = rec.room().unwrap_or_else(|e| panic!("Failed to rebuild room: {}", e));
+    let mut speed: f32 = opts.get("speed", 10.0);
+    let mut paused = false;
+    let mut desync = false;
+
+    terminal::enable_raw_mode().expect("Failed to enable RAW mode.");
+    _ = stdout().execute(cursor::Hide);
+    _ = room.draw(true);
+
+    let mut step = 0;
+    loop {
+        _ = draw_status(&room, step, rec.len(), speed, paused, desync);
+        let timeout = if paused || step >= rec.len() {
+            Duration::from_secs(3600)
+        } else {
+            Duration::from_secs_f32(1.0 / speed)
+        };
+        let mut advance = !paused;
+        if event::poll(timeout).unwrap_or(false) {
+            advance = false;
+            if let Ok(Event::Key(event::KeyEvent{code, ..})) = event::read() {
+                match code {
+                    event::KeyCode::Char(' ') => paused = !paused,
+                    event::KeyCode::Char('n') | event::KeyCode::Right => advance = paused,
+                    event::KeyCode::Char('+') => speed *= 2.0,
+                    event::KeyCode::Char('-') => speed = (speed / 2.0).max(0.5),
+                    event::KeyCode::Esc | event::KeyCode::Char('q') => break,
+                    _ => {}
+                }
+            }
+        }
+        if advance && step < rec.len() {
+            let (actions, r) = &rec.steps[step];
+            if actions.is_empty() {
+                room.reset();
+                _ = room.draw(true);
+            } else {
+                let actions: Vec<game::Action> = actions.iter().map(|a| game::i_to_act(*a)).collect();
+                desync |= room.step(&actions).iter().sum::<f32>() != *r;
+                _ = room.draw(false);
+            }
+            step += 1;
+        }
+    }
+
+    terminal::disable_raw_mode().expect("Failed to disable RAW mode.");
+    _ = stdout().execute(terminal::Clear(terminal::ClearType::All));
+    _ = stdout().execute(cursor::Show);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir().join(format!("robovac-{}-{}.replay", name, std::process::id()))
+            .to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut room = Room::with_config(30, 20, 7, LevelConfig::default());
+        let mut rec = Recording::start(&room, vec![("mode".to_string(), "user".to_string()),
+                                                   ("policy".to_string(), "my policy.ot".to_string())]);
+        for a in [0, 0, 2, 4, 1] {
+            let r = room.step(&[game::i_to_act(a)]).iter().sum();
+            rec.push(vec![a], r);
+        }
+        rec.push_reset();
+        rec.push(vec![3], 0.5);
+
+        let path = temp_path("round-trip");
+        rec.save(&path).unwrap();
+        let loaded = Recording::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!((loaded.xsize, loaded.ysize, loaded.seed), (30, 20, Some(7)));
+        assert_eq!(loaded.config, rec.config);
+        assert_eq!(loaded.level, rec.level);
+        assert_eq!(loaded.board, rec.board);
+        assert_eq!(loaded.steps, rec.steps);
+        assert_eq!(loaded.room().unwrap().get_map(), rec.board);
+    }
+
+    #[test]
+    fn level_flags_round_trip() {
+        let flags = ["diagonal", "continuous", "block-no-go", "return-to-dock"];
+        let mut pairs: Vec<(String, String)> = flags.iter().map(|f| (f.to_string(), String::new())).collect();
+        pairs.push(("no-go".to_string(), "20,5,24,9".to_string()));
+        let config = LevelConfig::from_options(&Options::from_pairs(&pairs));
+        let room = Room::with_config(40, 25, 3, config);
+        let rec = Recording::start(&room, Vec::new());
+
+        let path = temp_path("flags");
+        rec.save(&path).unwrap();
+        let loaded = Recording::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.level, rec.level);
+        let rebuilt = loaded.room().unwrap();
+        assert_eq!(rebuilt.get_config().to_options(), room.get_config().to_options());
+        for flag in flags {
+            assert!(loaded.level.iter().any(|(k, _)| k == flag), "--{} was lost", flag);
+        }
+    }
+
+    #[test]
+    fn load_rejects_unknown_lines() {
+        let path = temp_path("bad-line");
+        fs::write(&path, "robovac-replay 1\nsize 30 20\nsomething else\nsteps\n").unwrap();
+        let err = Recording::load(&path).err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(err.map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+    }
+}